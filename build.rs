@@ -6,11 +6,18 @@ use std::process::Command;
 
 const CLIB_DIR: &str = "c_lib";
 const CLIB_THIRD_PARTY_DIR: &str = "c_lib/third_party";
+const CLIB_MOCK_INCLUDE_DIR: &str = "c_lib/mock/include";
 
-fn main() {
+fn build_clib() {
     let mut make = Command::new("make");
+    let mut cflags: Vec<&str> = Vec::new();
     #[cfg(feature = "hterm")]
-    make.arg("CFLAGS=-DHTERM");
+    cflags.push("-DHTERM");
+    #[cfg(feature = "sockets")]
+    cflags.push("-DSOCKETS");
+    if !cflags.is_empty() {
+        make.arg(format!("CFLAGS={}", cflags.join(" ")));
+    }
     if make
         .arg("-C")
         .arg(CLIB_DIR)
@@ -25,6 +32,22 @@ fn main() {
     println!("cargo:rustc-link-search={CLIB_DIR}/bin/");
 
     println!("cargo:rustc-link-lib=static=wasi_ext_lib");
+}
+
+fn main() {
+    // With the mock feature the C library is implemented in src/mock.rs, so
+    // nothing is linked and bindings are generated against host headers. The
+    // stub headers are searched last to only fill in what the host lacks
+    let sysroot_arg = if cfg!(feature = "mock") {
+        format!("-idirafter{CLIB_MOCK_INCLUDE_DIR}")
+    } else {
+        build_clib();
+        format!(
+            "--sysroot={}/share/wasi-sysroot",
+            env::var("WASI_SDK_PATH").expect("WASI_SDK_PATH is undefined")
+        )
+    };
+
     println!("cargo:rerun-if-changed={CLIB_DIR}");
     println!("cargo:rerun-if-changed={CLIB_THIRD_PARTY_DIR}/termios");
     println!("cargo:rerun-if-changed={CLIB_THIRD_PARTY_DIR}/termios/bits");
@@ -35,33 +58,30 @@ fn main() {
 
     // termios lib
     bgen = bindgen::Builder::default().header(format!("{CLIB_THIRD_PARTY_DIR}/termios/termios.h"));
-    bgen.clang_arg(format!(
-        "--sysroot={}/share/wasi-sysroot",
-        env!("WASI_SDK_PATH")
-    ))
-    .clang_arg("-fvisibility=default")
-    .allowlist_file(format!("{CLIB_THIRD_PARTY_DIR}/termios/termios.h"))
-    .allowlist_file(format!("{CLIB_THIRD_PARTY_DIR}/termios/bits/termios.h"))
-    .parse_callbacks(Box::new(bindgen::CargoCallbacks))
-    .generate()
-    .expect("Unable to generate bindings")
-    .write_to_file("src/termios_generated.rs")
-    .expect("could not write termios bindings");
+    bgen.clang_arg(&sysroot_arg)
+        .clang_arg("-fvisibility=default")
+        .allowlist_file(format!("{CLIB_THIRD_PARTY_DIR}/termios/termios.h"))
+        .allowlist_file(format!("{CLIB_THIRD_PARTY_DIR}/termios/bits/termios.h"))
+        .parse_callbacks(Box::new(bindgen::CargoCallbacks))
+        .generate()
+        .expect("Unable to generate bindings")
+        .write_to_file("src/termios_generated.rs")
+        .expect("could not write termios bindings");
 
     // general lib
     bgen = bindgen::Builder::default().header(format!("{CLIB_DIR}/wasi_ext_lib.h"));
     if cfg!(feature = "hterm") {
         bgen = bgen.clang_arg("-DHTERM");
     }
-    bgen.clang_arg(format!(
-        "--sysroot={}/share/wasi-sysroot",
-        env!("WASI_SDK_PATH")
-    ))
-    .clang_arg("-fvisibility=default")
-    .allowlist_file(format!("{CLIB_DIR}/wasi_ext_lib.h"))
-    .parse_callbacks(Box::new(bindgen::CargoCallbacks))
-    .generate()
-    .expect("Unable to generate bindings")
-    .write_to_file("src/wasi_ext_lib_generated.rs")
-    .expect("could not write bindings");
+    if cfg!(feature = "sockets") {
+        bgen = bgen.clang_arg("-DSOCKETS");
+    }
+    bgen.clang_arg(&sysroot_arg)
+        .clang_arg("-fvisibility=default")
+        .allowlist_file(format!("{CLIB_DIR}/wasi_ext_lib.h"))
+        .parse_callbacks(Box::new(bindgen::CargoCallbacks))
+        .generate()
+        .expect("Unable to generate bindings")
+        .write_to_file("src/wasi_ext_lib_generated.rs")
+        .expect("could not write bindings");
 }
@@ -0,0 +1,17 @@
+/*
+ * Copyright (c) 2022-2023 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+// Host stand-in for the musl header, termios.h only needs pid_t and struct
+// winsize from it. Used to generate bindings for the mock feature
+
+#include <sys/types.h>
+
+#if defined(__NEED_struct_winsize) && !defined(__DEFINED_struct_winsize)
+struct winsize {
+    unsigned short ws_row, ws_col, ws_xpixel, ws_ypixel;
+};
+#define __DEFINED_struct_winsize
+#endif
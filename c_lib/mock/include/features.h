@@ -0,0 +1,8 @@
+/*
+ * Copyright (c) 2022-2023 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+// Empty fallback for hosts without a libc features.h, e.g. macOS. Host
+// headers are searched first, so glibc and musl keep their own
@@ -0,0 +1,17 @@
+/*
+ * Copyright (c) 2022-2023 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+#ifndef c_bindings_mock_wasi_api_h_INCLUDED
+#define c_bindings_mock_wasi_api_h_INCLUDED
+
+// Host stand-in for the wasi-sdk header, only the types used by
+// wasi_ext_lib.h are defined. Used to generate bindings for the mock feature
+
+#include <stdint.h>
+
+typedef uint16_t __wasi_fdflags_t;
+typedef uint8_t __wasi_filetype_t;
+
+#endif
@@ -91,6 +91,8 @@ struct termios {
 #define B3500000 0010016
 #define B4000000 0010017
 
+#define CBAUD    0010017
+
 #define CSIZE  0000060
 #define CS5    0000000
 #define CS6    0000020
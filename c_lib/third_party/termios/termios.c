@@ -8,22 +8,69 @@
 
 speed_t wasi_ext_cfgetospeed (const struct termios * termios_p)
 {
-    return -ENOTSUP;
+    return termios_p->c_cflag & CBAUD;
 }
 
 speed_t wasi_ext_cfgetispeed (const struct termios * termios_p)
 {
-    return -ENOTSUP;
+    return wasi_ext_cfgetospeed(termios_p);
+}
+
+// Only the B* constants are speeds, other values within CBAUD are not
+static int is_valid_speed (speed_t speed)
+{
+    switch (speed) {
+    case B0:
+    case B50:
+    case B75:
+    case B110:
+    case B134:
+    case B150:
+    case B200:
+    case B300:
+    case B600:
+    case B1200:
+    case B1800:
+    case B2400:
+    case B4800:
+    case B9600:
+    case B19200:
+    case B38400:
+    case B57600:
+    case B115200:
+    case B230400:
+    case B460800:
+    case B500000:
+    case B576000:
+    case B921600:
+    case B1000000:
+    case B1152000:
+    case B1500000:
+    case B2000000:
+    case B2500000:
+    case B3000000:
+    case B3500000:
+    case B4000000:
+        return 1;
+    default:
+        return 0;
+    }
 }
 
 int wasi_ext_cfsetospeed (struct termios * termios_p, speed_t speed)
 {
-    return -ENOTSUP;
+    if (!is_valid_speed(speed)) {
+        return -EINVAL;
+    }
+    termios_p->c_cflag &= ~CBAUD;
+    termios_p->c_cflag |= speed;
+    return 0;
 }
 
 int wasi_ext_cfsetispeed (struct termios * termios_p, speed_t speed)
 {
-    return -ENOTSUP;
+    // input and output speeds are shared, 0 means "same as output"
+    return speed ? wasi_ext_cfsetospeed(termios_p, speed) : 0;
 }
 
 int wasi_ext_tcgetattr(int fd, struct termios *tio)
@@ -60,32 +107,57 @@ int wasi_ext_tcgetwinsize (int fd, struct winsize * winsize_p)
 
 int wasi_ext_tcsetwinsize (int fd, const struct winsize * winsize_p)
 {
-    return -ENOTSUP;
+    // kernel rejects non-tty fds and notifies WINCH listeners on success
+    return -wasi_ext_ioctl(fd, TIOCSWINSZ, (void*)winsize_p);
 }
 
 int wasi_ext_tcsendbreak (int fd, int duration)
 {
-    return -ENOTSUP;
+    // zero argument to TCSBRK sends a break, duration is ignored like in musl
+    int arg = 0;
+    return -wasi_ext_ioctl(fd, TCSBRK, (void*)&arg);
 }
 
 int wasi_ext_tcdrain (int fd)
 {
-    return -ENOTSUP;
+    // nonzero argument makes TCSBRK only wait for the output, like in musl
+    int arg = 1;
+    return -wasi_ext_ioctl(fd, TCSBRK, (void*)&arg);
 }
 
-int wasi_ext_tcflush (int fd, int duration)
+int wasi_ext_tcflush (int fd, int queue)
 {
-    return -ENOTSUP;
+    return -wasi_ext_ioctl(fd, TCFLSH, (void*)&queue);
 }
 
 int wasi_ext_tcflow (int fd, int action)
 {
-    return -ENOTSUP;
+    return -wasi_ext_ioctl(fd, TCXONC, (void*)&action);
 }
 
 pid_t wasi_ext_tcgetsid (int fd)
 {
-    return -ENOTSUP;
+    pid_t sid;
+    int err = wasi_ext_ioctl(fd, TIOCGSID, (void*)&sid);
+    if (err < 0) {
+        return err;
+    }
+    return sid;
+}
+
+pid_t wasi_ext_tcgetpgrp (int fd)
+{
+    pid_t pgrp;
+    int err = wasi_ext_ioctl(fd, TIOCGPGRP, (void*)&pgrp);
+    if (err < 0) {
+        return err;
+    }
+    return pgrp;
+}
+
+int wasi_ext_tcsetpgrp (int fd, pid_t pgrp)
+{
+    return -wasi_ext_ioctl(fd, TIOCSPGRP, (void*)&pgrp);
 }
 
 void wasi_ext_cfmakeraw(struct termios * termios_p)
@@ -101,5 +173,5 @@ void wasi_ext_cfmakeraw(struct termios * termios_p)
 
 int wasi_ext_cfsetspeed(struct termios * termios_p, speed_t speed)
 {
-    return -ENOTSUP;
+    return wasi_ext_cfsetospeed(termios_p, speed);
 }
\ No newline at end of file
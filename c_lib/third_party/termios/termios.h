@@ -35,14 +35,14 @@ typedef unsigned int tcflag_t;
 #define TCSETA		0x5406
 #define TCSETAW		0x5407
 #define TCSETAF		0x5408
-#define TCSBRK		0x5409
-#define TCXONC		0x540A
-#define TCFLSH		0x540B
+#define TCSBRK		0x40045409
+#define TCXONC		0x4004540A
+#define TCFLSH		0x4004540B
 #define TIOCEXCL	0x540C
 #define TIOCNXCL	0x540D
 #define TIOCSCTTY	0x540E
-#define TIOCGPGRP	0x540F
-#define TIOCSPGRP	0x5410
+#define TIOCGPGRP	0x8004540F
+#define TIOCSPGRP	0x40045410
 #define TIOCOUTQ	0x5411
 #define TIOCSTI		0x5412
 #define TIOCGWINSZ	0x80085413
@@ -67,7 +67,7 @@ typedef unsigned int tcflag_t;
 #define TCSBRKP		0x5425
 #define TIOCSBRK	0x5427
 #define TIOCCBRK	0x5428
-#define TIOCGSID	0x5429
+#define TIOCGSID	0x80045429
 #define TIOCGRS485	0x542E
 #define TIOCSRS485	0x542F
 #define TIOCGPTN	0x80045430
@@ -126,6 +126,10 @@ typedef unsigned int tcflag_t;
 #define SIOCGSTAMP      0x8906
 #define SIOCGSTAMPNS    0x8907
 
+// tcgetsid and tcgetpgrp return the id or a negated errno, cfset* functions
+// return 0 or a negated errno and the remaining tc* functions return 0 or a
+// positive errno. tcsetattr is mixed, it rejects actions other than TCSANOW
+// with a negated errno and reports ioctl failures with a positive one
 speed_t wasi_ext_cfgetospeed (const struct termios *);
 speed_t wasi_ext_cfgetispeed (const struct termios *);
 int wasi_ext_cfsetospeed (struct termios *, speed_t);
@@ -144,6 +148,9 @@ int wasi_ext_tcflow (int, int);
 
 pid_t wasi_ext_tcgetsid (int);
 
+pid_t wasi_ext_tcgetpgrp (int);
+int wasi_ext_tcsetpgrp (int, pid_t);
+
 void wasi_ext_cfmakeraw(struct termios *);
 int wasi_ext_cfsetspeed(struct termios *, speed_t);
 
@@ -19,8 +19,8 @@
 #define SYSCALL_LENGTH 256
 #define SYSCALL_ARGS_LENGTH 2048
 
-int __syscall(const char *command, char *args, uint8_t *output_buf,
-              size_t output_buf_len) {
+int __syscall_written(const char *command, char *args, uint8_t *output_buf,
+                      size_t output_buf_len, size_t *written) {
     char *ptr;
     asprintf(&ptr, "%p", args);
     JsonNode *root = json_mkobject();
@@ -31,14 +31,22 @@ int __syscall(const char *command, char *args, uint8_t *output_buf,
     char *serialized = json_stringify(1, root, " ");
     json_delete(root);
 
-    size_t written;
     int err = __wasi_path_readlink(3, serialized, output_buf, output_buf_len,
-                                   &written);
+                                   written);
     free(ptr);
     free(serialized);
     return err;
 }
 
+int __syscall(const char *command, char *args, uint8_t *output_buf,
+              size_t output_buf_len) {
+    size_t written;
+    return __syscall_written(command, args, output_buf, output_buf_len,
+                             &written);
+}
+
+int wasi_ext_errno() { return errno; }
+
 int wasi_ext_mount(int source_fd, const char *source_path, int target_fd,
                    const char *target_path, const char *filesystem_type,
                    uint64_t mount_flags, const char *data) {
@@ -104,6 +112,19 @@ int wasi_ext_chdir(const char *path) {
     return err;
 }
 
+int wasi_ext_fchdir(int fd) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "fd", json_mknumber(fd));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("fchdir", serialized, NULL, 0);
+    free(serialized);
+
+    return err;
+}
+
 int wasi_ext_getcwd(char *path, size_t buf_len) {
     JsonNode *root = json_mkobject();
     json_append_member(root, "buf_len", json_mknumber((double)buf_len));
@@ -116,6 +137,30 @@ int wasi_ext_getcwd(char *path, size_t buf_len) {
     return err;
 }
 
+int wasi_ext_gethostname(char *name, size_t buf_len) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "buf_len", json_mknumber((double)buf_len));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("gethostname", serialized, (uint8_t *)name, buf_len);
+    free(serialized);
+    return err;
+}
+
+int wasi_ext_sethostname(const char *name) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "name", json_mkstring(name));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("sethostname", serialized, NULL, 0);
+    free(serialized);
+    return err;
+}
+
 int wasi_ext_isatty(int fd) {
     const size_t output_len = 64;
     char output[output_len];
@@ -135,6 +180,20 @@ int wasi_ext_isatty(int fd) {
     return res;
 }
 
+int wasi_ext_ttyname(int fd, char *buf, size_t buf_len) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "fd", json_mknumber(fd));
+    json_append_member(root, "buf_len", json_mknumber((double)buf_len));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("ttyname", serialized, (uint8_t *)buf, buf_len);
+    free(serialized);
+
+    return err;
+}
+
 int wasi_ext_set_env(const char *attrib, const char *val) {
     if (val == NULL) {
         if (unsetenv(attrib) != 0) {
@@ -159,6 +218,31 @@ int wasi_ext_set_env(const char *attrib, const char *val) {
     return err;
 }
 
+int wasi_ext_get_env(const char *attrib, char *val, size_t buf_len) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "key", json_mkstring(attrib));
+    json_append_member(root, "buf_len", json_mknumber((double)buf_len));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("get_env", serialized, (uint8_t *)val, buf_len);
+    free(serialized);
+    return err;
+}
+
+int wasi_ext_environ(char *buf, size_t buf_len) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "buf_len", json_mknumber((double)buf_len));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("environ", serialized, (uint8_t *)buf, buf_len);
+    free(serialized);
+    return err;
+}
+
 int wasi_ext_getpid() {
     char args[] = "{}";
     const size_t output_len = 16;
@@ -172,6 +256,83 @@ int wasi_ext_getpid() {
     }
 }
 
+int wasi_ext_getppid() {
+    char args[] = "{}";
+    const size_t output_len = 4;
+    char output[output_len];
+    int result = __syscall("getppid", args, (uint8_t *)output, output_len);
+    if (result != 0) {
+        return -result;
+    } else {
+        int res = *((int *)output);
+        return res;
+    }
+}
+
+int wasi_ext_getpgid(int pid) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "pid", json_mknumber(pid));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    const size_t output_len = 4;
+    char output[output_len];
+
+    int err = __syscall("getpgid", serialized, (uint8_t *)output, output_len);
+    free(serialized);
+    if (err != 0) {
+        return -err;
+    }
+    int res = *((int *)output);
+    return res;
+}
+
+int wasi_ext_getsid(int pid) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "pid", json_mknumber(pid));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    const size_t output_len = 4;
+    char output[output_len];
+
+    int err = __syscall("getsid", serialized, (uint8_t *)output, output_len);
+    free(serialized);
+    if (err != 0) {
+        return -err;
+    }
+    int res = *((int *)output);
+    return res;
+}
+
+int wasi_ext_setsid() {
+    char args[] = "{}";
+    const size_t output_len = 4;
+    char output[output_len];
+    int result = __syscall("setsid", args, (uint8_t *)output, output_len);
+    if (result != 0) {
+        return -result;
+    } else {
+        int res = *((int *)output);
+        return res;
+    }
+}
+
+int wasi_ext_setpgid(int pid, int pgid) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "pid", json_mknumber(pid));
+    json_append_member(root, "pgid", json_mknumber(pgid));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("setpgid", serialized, NULL, 0);
+    free(serialized);
+    return -err;
+}
+
 #ifdef HTERM
 int wasi_ext_event_source_fd(uint32_t event_mask) {
     JsonNode *root = json_mkobject();
@@ -204,6 +365,19 @@ int wasi_ext_attach_sigint(int32_t fd) {
     free(serialized);
     return -err;
 }
+
+int wasi_ext_attach_signal(int32_t fd, int signal) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "event_source_fd", json_mknumber(fd));
+    json_append_member(root, "signal", json_mknumber(signal));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("attach_signal", serialized, NULL, 0);
+    free(serialized);
+    return -err;
+}
 #endif
 
 int wasi_ext_clean_inodes() {
@@ -212,6 +386,21 @@ int wasi_ext_clean_inodes() {
     return __syscall("clean_inodes", "{}", (uint8_t *)output, output_len);
 }
 
+int wasi_ext_clean_inodes_report(int *reclaimed) {
+    const size_t output_len = 4;
+    char output[output_len];
+    size_t written = 0;
+    int err = __syscall_written("clean_inodes", "{}", (uint8_t *)output,
+                                output_len, &written);
+    if (err != 0) {
+        return err;
+    }
+
+    // older kernels do not report the count
+    *reclaimed = written == output_len ? *((int *)output) : -1;
+    return 0;
+}
+
 int wasi_ext_spawn(const char *path, const char *const *args, size_t n_args,
                    const struct Env *env, size_t n_env, int background,
                    const struct Redirect *redirects, size_t n_redirects,
@@ -241,8 +430,11 @@ int wasi_ext_spawn(const char *path, const char *const *args, size_t n_args,
     char *call_args = json_stringify(0, root, " ");
     json_delete(root);
 
+    // kernel writes spawn status and child pid, for foreground processes the
+    // syscall result carries the exit code of the child
     const size_t output_len = 8;
     char buf[output_len];
+    memset(buf, 0, output_len);
     int result = __syscall("spawn", call_args, (uint8_t *)buf, output_len);
     free(call_args);
     int *data_ptr = (int *)buf;
@@ -268,6 +460,68 @@ int wasi_ext_kill(int pid, int sig) {
     return -err;
 }
 
+int wasi_ext_waitpid(int pid, int options, int *status) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "pid", json_mknumber(pid));
+    json_append_member(root, "options", json_mknumber(options));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    const size_t output_len = 8;
+    char buf[output_len];
+    int err = __syscall("waitpid", serialized, (uint8_t *)buf, output_len);
+    free(serialized);
+    if (err != 0) {
+        return -err;
+    }
+
+    int *data_ptr = (int *)buf;
+    *status = data_ptr[1];
+    return data_ptr[0];
+}
+
+int wasi_ext_renice(int pid, int delta, int *priority) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "pid", json_mknumber(pid));
+    json_append_member(root, "delta", json_mknumber(delta));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    const size_t output_len = 4;
+    char output[output_len];
+    int err = __syscall("renice", serialized, (uint8_t *)output, output_len);
+    free(serialized);
+    if (err != 0) {
+        return -err;
+    }
+
+    *priority = *((int *)output);
+    return 0;
+}
+
+int wasi_ext_sigprocmask(int how, uint32_t set, uint32_t *oldset) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "how", json_mknumber(how));
+    json_append_member(root, "set", json_mknumber(set));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    const size_t output_len = 4;
+    char output[output_len];
+    int err =
+        __syscall("sigprocmask", serialized, (uint8_t *)output, output_len);
+    free(serialized);
+    if (err != 0) {
+        return -err;
+    }
+
+    *oldset = *((uint32_t *)output);
+    return 0;
+}
+
 int wasi_ext_ioctl(int fd, unsigned int cmd, void *arg) {
     JsonNode *root = json_mkobject();
     json_append_member(root, "fd", json_mknumber(fd));
@@ -283,9 +537,50 @@ int wasi_ext_ioctl(int fd, unsigned int cmd, void *arg) {
     return -err;
 }
 
+int wasi_ext_ioctl_buf(int fd, unsigned int cmd, void *buf, size_t buf_len) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "fd", json_mknumber(fd));
+    json_append_member(root, "cmd", json_mknumber(cmd));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    size_t written;
+    int err = __syscall_written("ioctl", serialized, buf, buf_len, &written);
+
+    free(serialized);
+
+    if (err != 0) {
+        return -err;
+    }
+    return (int)written;
+}
+
+static int fcntl_lock(int fd, enum FcntlCommand cmd, struct Flock *lock) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "fd", json_mknumber(fd));
+    json_append_member(root, "cmd", json_mknumber(cmd));
+    json_append_member(root, "type", json_mknumber(lock->type));
+    json_append_member(root, "whence", json_mknumber(lock->whence));
+    json_append_member(root, "start", json_mknumber(lock->start));
+    json_append_member(root, "len", json_mknumber(lock->len));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    // F_GETLK overwrites the descriptor with the conflicting lock, or sets its
+    // type to F_UNLCK if there is none
+    size_t output_len = F_GETLK == cmd ? sizeof(*lock) : 0;
+    int err = __syscall("fcntl_lock", serialized, (uint8_t *)lock, output_len);
+    free(serialized);
+
+    return -err;
+}
+
 int wasi_ext_fcntl(int fd, enum FcntlCommand cmd, void *arg) {
     __wasi_errno_t err;
     switch (cmd) {
+    case F_DUPFD:
     case F_MVFD: {
         int min_fd = *((int *)arg);
         __wasi_fdstat_t stat;
@@ -301,7 +596,7 @@ int wasi_ext_fcntl(int fd, enum FcntlCommand cmd, void *arg) {
         }
 
         if (min_fd >= _MAX_FD_NUM) {
-            return __WASI_ERRNO_MFILE;
+            return -__WASI_ERRNO_MFILE;
         }
 
         // We assume fd_renumber behaves like dup2
@@ -310,9 +605,11 @@ int wasi_ext_fcntl(int fd, enum FcntlCommand cmd, void *arg) {
             return -err;
         }
 
-        err = __wasi_fd_close(fd);
-        if (__WASI_ERRNO_SUCCESS != err) {
-            return -err;
+        if (F_MVFD == cmd) {
+            err = __wasi_fd_close(fd);
+            if (__WASI_ERRNO_SUCCESS != err) {
+                return -err;
+            }
         }
 
         // like F_DUPFD, return allocated fd
@@ -331,29 +628,502 @@ int wasi_ext_fcntl(int fd, enum FcntlCommand cmd, void *arg) {
         return (int)flags;
     }
     case F_SETFD: {
-        __wasi_fdflags_t flags = *((__wasi_fdflags_t *)arg);
+        // drop standard flags so the value returned by F_GETFD round-trips,
         // set control bit to enable extended flags processing
-        flags |= WASI_EXT_FDFLAG_CTRL_BIT;
+        __wasi_fdflags_t flags =
+            (*((__wasi_fdflags_t *)arg) & WASI_EXT_FDFLAG_MASK) |
+            WASI_EXT_FDFLAG_CTRL_BIT;
+
+        err = __wasi_fd_fdstat_set_flags(fd, flags);
+
+        return -err;
+    }
+    case F_GETFL: {
+        __wasi_fdstat_t stat;
+        err = __wasi_fd_fdstat_get(fd, &stat);
+
+        if (__WASI_ERRNO_SUCCESS != err) {
+            return -err;
+        }
+
+        // only standard wasi flags, extended ones are reported by F_GETFD
+        __wasi_fdflags_t flags =
+            stat.fs_flags & ~(WASI_EXT_FDFLAG_MASK | WASI_EXT_FDFLAG_CTRL_BIT);
+
+        return (int)flags;
+    }
+    case F_SETFL: {
+        __wasi_fdflags_t flags = *((__wasi_fdflags_t *)arg);
+        // control bit must stay cleared so extended flags are not touched
+        flags &= ~(WASI_EXT_FDFLAG_MASK | WASI_EXT_FDFLAG_CTRL_BIT);
 
         err = __wasi_fd_fdstat_set_flags(fd, flags);
 
         return -err;
     }
+    case F_GETLK:
+    case F_SETLK:
+    case F_SETLKW: {
+        return fcntl_lock(fd, cmd, (struct Flock *)arg);
+    }
     }
 
     return -EINVAL;
 }
 
-int wasi_ext_mknod(const char *path, int dev) {
+int wasi_ext_pipe(int *fds, __wasi_fdflags_t flags) {
     JsonNode *root = json_mkobject();
-    json_append_member(root, "path", json_mkstring(path));
-    json_append_member(root, "dev", json_mknumber(dev));
+    json_append_member(root, "flags", json_mknumber(flags));
 
     char *serialized = json_stringify(0, root, " ");
     json_delete(root);
 
-    int err = __syscall("mknod", serialized, NULL, 0);
+    const size_t output_len = 8;
+    char buf[output_len];
+    int err = __syscall("pipe", serialized, (uint8_t *)buf, output_len);
     free(serialized);
+    if (err != 0) {
+        return -err;
+    }
 
-    return err;
+    int *data_ptr = (int *)buf;
+    fds[0] = data_ptr[0];
+    fds[1] = data_ptr[1];
+    return 0;
+}
+
+int wasi_ext_close_range(unsigned int first, unsigned int last,
+                         unsigned int flags) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "first", json_mknumber(first));
+    json_append_member(root, "last", json_mknumber(last));
+    json_append_member(root, "flags", json_mknumber(flags));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("close_range", serialized, NULL, 0);
+    free(serialized);
+
+    return err;
+}
+
+int wasi_ext_memfd_create(const char *name, unsigned int flags) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "name", json_mkstring(name));
+    json_append_member(root, "flags", json_mknumber(flags));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    const size_t output_len = 4;
+    char output[output_len];
+    int err =
+        __syscall("memfd_create", serialized, (uint8_t *)output, output_len);
+    free(serialized);
+    if (err != 0) {
+        return -err;
+    }
+
+    int fd = *((int *)output);
+    return fd;
 }
+
+// Copies within the kernel starting at the current offsets of both fds, which
+// are advanced by the number of bytes copied
+int wasi_ext_copy_file_range(int src_fd, int dst_fd, size_t len) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "src_fd", json_mknumber(src_fd));
+    json_append_member(root, "dst_fd", json_mknumber(dst_fd));
+    json_append_member(root, "len", json_mknumber((double)len));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    const size_t output_len = 4;
+    char output[output_len];
+    int err =
+        __syscall("copy_file_range", serialized, (uint8_t *)output, output_len);
+    free(serialized);
+    if (err != 0) {
+        return -err;
+    }
+
+    int copied = *((int *)output);
+    return copied;
+}
+
+int wasi_ext_mknod(const char *path, int dev) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "path", json_mkstring(path));
+    json_append_member(root, "dev", json_mknumber(dev));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("mknod", serialized, NULL, 0);
+    free(serialized);
+
+    return err;
+}
+
+int wasi_ext_umask(int mask) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "mask", json_mknumber(mask));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    const size_t output_len = 4;
+    char output[output_len];
+
+    int err = __syscall("umask", serialized, (uint8_t *)output, output_len);
+    free(serialized);
+    if (err != 0) {
+        return -err;
+    }
+    int res = *((int *)output);
+    return res;
+}
+
+int wasi_ext_getrlimit(int resource, struct Rlimit *limit) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "resource", json_mknumber(resource));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err =
+        __syscall("getrlimit", serialized, (uint8_t *)limit, sizeof(*limit));
+    free(serialized);
+
+    return err;
+}
+
+// JSON numbers are doubles, RLIM_INFINITY is sent as null so it stays exact
+static JsonNode *rlim_to_json(uint64_t value) {
+    if (RLIM_INFINITY == value) {
+        return json_mknull();
+    }
+    return json_mknumber((double)value);
+}
+
+int wasi_ext_setrlimit(int resource, const struct Rlimit *limit) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "resource", json_mknumber(resource));
+    json_append_member(root, "cur", rlim_to_json(limit->cur));
+    json_append_member(root, "max", rlim_to_json(limit->max));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("setrlimit", serialized, NULL, 0);
+    free(serialized);
+
+    return err;
+}
+
+int wasi_ext_symlink(const char *target, const char *linkpath) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "target", json_mkstring(target));
+    json_append_member(root, "linkpath", json_mkstring(linkpath));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("symlink", serialized, NULL, 0);
+    free(serialized);
+
+    return err;
+}
+
+int wasi_ext_readlink(const char *path, char *buf, size_t buf_len) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "path", json_mkstring(path));
+    json_append_member(root, "buf_len", json_mknumber((double)buf_len));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("readlink", serialized, (uint8_t *)buf, buf_len);
+    free(serialized);
+
+    return err;
+}
+
+int wasi_ext_realpath(const char *path, char *buf, size_t buf_len) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "path", json_mkstring(path));
+    json_append_member(root, "buf_len", json_mknumber((double)buf_len));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("realpath", serialized, (uint8_t *)buf, buf_len);
+    free(serialized);
+
+    return err;
+}
+
+int wasi_ext_access(const char *path, int mode) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "path", json_mkstring(path));
+    json_append_member(root, "mode", json_mknumber(mode));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("access", serialized, NULL, 0);
+    free(serialized);
+
+    return err;
+}
+
+int wasi_ext_mkdir(const char *path, int mode) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "path", json_mkstring(path));
+    json_append_member(root, "mode", json_mknumber(mode));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("mkdir", serialized, NULL, 0);
+    free(serialized);
+
+    return err;
+}
+
+int wasi_ext_rmdir(const char *path) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "path", json_mkstring(path));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("rmdir", serialized, NULL, 0);
+    free(serialized);
+
+    return err;
+}
+
+int wasi_ext_rename(const char *from, const char *to) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "from", json_mkstring(from));
+    json_append_member(root, "to", json_mkstring(to));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("rename", serialized, NULL, 0);
+    free(serialized);
+
+    return err;
+}
+
+int wasi_ext_chmod(const char *path, int mode) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "path", json_mkstring(path));
+    json_append_member(root, "mode", json_mknumber(mode));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("chmod", serialized, NULL, 0);
+    free(serialized);
+
+    return err;
+}
+
+int wasi_ext_fchmod(int fd, int mode) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "fd", json_mknumber(fd));
+    json_append_member(root, "mode", json_mknumber(mode));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("fchmod", serialized, NULL, 0);
+    free(serialized);
+
+    return err;
+}
+
+int wasi_ext_truncate(const char *path, uint64_t len) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "path", json_mkstring(path));
+    json_append_member(root, "len", json_mknumber((double)len));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("truncate", serialized, NULL, 0);
+    free(serialized);
+
+    return err;
+}
+
+int wasi_ext_flock(int fd, int op) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "fd", json_mknumber(fd));
+    json_append_member(root, "op", json_mknumber(op));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("flock", serialized, NULL, 0);
+    free(serialized);
+
+    return err;
+}
+
+int wasi_ext_stat(const char *path, int follow_symlinks, struct Stat *stat) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "path", json_mkstring(path));
+    json_append_member(root, "follow_symlinks",
+                       json_mkbool(follow_symlinks != 0));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("stat", serialized, (uint8_t *)stat, sizeof(*stat));
+    free(serialized);
+
+    return err;
+}
+
+int wasi_ext_fstat(int fd, struct Stat *stat) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "fd", json_mknumber(fd));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("fstat", serialized, (uint8_t *)stat, sizeof(*stat));
+    free(serialized);
+
+    return err;
+}
+
+int wasi_ext_statvfs(const char *path, struct StatVfs *stat) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "path", json_mkstring(path));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("statvfs", serialized, (uint8_t *)stat, sizeof(*stat));
+    free(serialized);
+
+    return err;
+}
+
+int wasi_ext_link(const char *existing, const char *new) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "existing", json_mkstring(existing));
+    json_append_member(root, "new", json_mkstring(new));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("link", serialized, NULL, 0);
+    free(serialized);
+
+    return err;
+}
+
+int wasi_ext_chown(const char *path, int uid, int gid) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "path", json_mkstring(path));
+    json_append_member(root, "uid", json_mknumber(uid));
+    json_append_member(root, "gid", json_mknumber(gid));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("chown", serialized, NULL, 0);
+    free(serialized);
+
+    return err;
+}
+
+int wasi_ext_fchown(int fd, int uid, int gid) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "fd", json_mknumber(fd));
+    json_append_member(root, "uid", json_mknumber(uid));
+    json_append_member(root, "gid", json_mknumber(gid));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("fchown", serialized, NULL, 0);
+    free(serialized);
+
+    return err;
+}
+
+#ifdef SOCKETS
+int wasi_ext_socket(int domain, int type, int protocol) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "domain", json_mknumber(domain));
+    json_append_member(root, "type", json_mknumber(type));
+    json_append_member(root, "protocol", json_mknumber(protocol));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    const size_t output_len = 4;
+    char output[output_len];
+
+    int err = __syscall("socket", serialized, (uint8_t *)output, output_len);
+    free(serialized);
+    if (err != 0) {
+        return -err;
+    }
+    int fd = *((int *)output);
+    return fd;
+}
+
+// Addresses are passed in their textual form, e.g. "127.0.0.1:8080" or
+// "[::1]:8080"
+int wasi_ext_bind(int fd, const char *addr) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "fd", json_mknumber(fd));
+    json_append_member(root, "addr", json_mkstring(addr));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("bind", serialized, NULL, 0);
+    free(serialized);
+
+    return err;
+}
+
+int wasi_ext_connect(int fd, const char *addr) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "fd", json_mknumber(fd));
+    json_append_member(root, "addr", json_mkstring(addr));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("connect", serialized, NULL, 0);
+    free(serialized);
+
+    return err;
+}
+
+int wasi_ext_listen(int fd, int backlog) {
+    JsonNode *root = json_mkobject();
+    json_append_member(root, "fd", json_mknumber(fd));
+    json_append_member(root, "backlog", json_mknumber(backlog));
+
+    char *serialized = json_stringify(0, root, " ");
+    json_delete(root);
+
+    int err = __syscall("listen", serialized, NULL, 0);
+    free(serialized);
+
+    return err;
+}
+#endif
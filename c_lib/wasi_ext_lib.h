@@ -56,8 +56,65 @@
 
 #define MKDEV(maj, min) ((maj << 20) | min)
 
+// Waitpid options
+#define WNOHANG 1
+#define WUNTRACED 2
+#define WCONTINUED 8
+
+// Waitpid statuses of stopped and continued children, as on Linux. Exited and
+// signalled children report plain exit codes
+#define WSTOPPED_MARK 0x7f
+#define WCONTINUED_STATUS 0xffff
+
+// Sigprocmask actions
+#define SIG_BLOCK 0
+#define SIG_UNBLOCK 1
+#define SIG_SETMASK 2
+
+// Flock operations
+#define LOCK_SH 1
+#define LOCK_EX 2
+#define LOCK_NB 4
+#define LOCK_UN 8
+
+// Close range flags
+#define CLOSE_RANGE_CLOEXEC 4
+
+// Memfd flags
+#define MFD_CLOEXEC 1
+
+// Resource limits
+#define RLIMIT_CPU 0
+#define RLIMIT_NOFILE 7
+#define RLIMIT_AS 9
+#define RLIM_INFINITY (~(uint64_t)0)
+
+// Mount flags
+#define MS_RDONLY 1
+#define MS_NOSUID 2
+#define MS_NODEV 4
+#define MS_NOEXEC 8
+#define MS_SYNCHRONOUS 16
+#define MS_REMOUNT 32
+#define MS_NOATIME 1024
+
+// Record lock types
+#define F_RDLCK 0
+#define F_WRLCK 1
+#define F_UNLCK 2
+
 // Fnctl commands
-enum FcntlCommand { F_MVFD, F_GETFD, F_SETFD };
+enum FcntlCommand {
+    F_MVFD,
+    F_GETFD,
+    F_SETFD,
+    F_DUPFD,
+    F_GETFL,
+    F_SETFL,
+    F_GETLK,
+    F_SETLK,
+    F_SETLKW
+};
 
 enum RedirectType {
     READ,
@@ -67,7 +124,8 @@ enum RedirectType {
     PIPEIN,
     PIPEOUT,
     DUPLICATE,
-    CLOSE
+    CLOSE,
+    READWRITEAPPEND
 };
 struct Redirect {
     union Data {
@@ -88,34 +146,140 @@ struct Env {
     const char *val;
 };
 
+// Metadata filled in by the kernel, timestamps are in nanoseconds
+struct Stat {
+    uint64_t dev;
+    uint64_t ino;
+    uint64_t rdev;
+    uint64_t nlink;
+    uint64_t size;
+    uint64_t atim;
+    uint64_t mtim;
+    uint64_t ctim;
+    uint32_t mode;
+    __wasi_filetype_t filetype;
+};
+
+// Byte-range lock used by F_GETLK, F_SETLK and F_SETLKW, whence takes
+// __wasi_whence_t values and len 0 extends the lock to the end of the file
+struct Flock {
+    int64_t start;
+    int64_t len;
+    int32_t pid;
+    int16_t type;
+    int16_t whence;
+};
+
+// Filesystem usage filled in by the kernel, block counts are in bsize units
+struct StatVfs {
+    uint64_t bsize;
+    uint64_t blocks;
+    uint64_t bfree;
+    uint64_t bavail;
+    uint64_t files;
+    uint64_t ffree;
+    uint64_t namemax;
+};
+
+struct Rlimit {
+    uint64_t cur;
+    uint64_t max;
+};
+
+#ifdef SOCKETS
+// Socket domains and types
+#define WASI_EXT_AF_INET 1
+#define WASI_EXT_AF_INET6 2
+#define WASI_EXT_SOCK_STREAM 1
+#define WASI_EXT_SOCK_DGRAM 2
+#endif
+
 #ifdef HTERM
 typedef uint32_t WasiEvents;
-#define WASI_EVENTS_NUM ((size_t)2)
+#define WASI_EVENTS_NUM ((size_t)4)
 #define WASI_EVENTS_MASK_SIZE ((size_t)4) // number of bytes
 // Hterm events
 #define WASI_EVENT_WINCH ((WasiEvents)(1 << 0))
 #define WASI_EVENT_SIGINT ((WasiEvents)(1 << 1))
+#define WASI_EVENT_SIGTERM ((WasiEvents)(1 << 2))
+#define WASI_EVENT_SIGHUP ((WasiEvents)(1 << 3))
 #endif
 
+// Failures are reported through the return value, errno is left as the libc
+// calls made by the library set it. Functions returning a value, i.e.
+// isatty, getpid, getppid, getpgid, getsid, setsid, event_source_fd, waitpid,
+// ioctl_buf, fcntl, memfd_create, copy_file_range, umask and socket, return a
+// negated errno on failure. So do setpgid, attach_sigint, attach_signal,
+// kill, renice, sigprocmask, ioctl and pipe, which return 0 on success, and
+// spawn, which returns the exit code of foreground processes. All other
+// functions return 0 or a positive errno
+int wasi_ext_errno();
 int wasi_ext_chdir(const char *);
+int wasi_ext_fchdir(int);
 int wasi_ext_getcwd(char *, size_t);
+int wasi_ext_gethostname(char *, size_t);
+int wasi_ext_sethostname(const char *);
 int wasi_ext_isatty(int);
+int wasi_ext_ttyname(int, char *, size_t);
 int wasi_ext_set_env(const char *, const char *);
+int wasi_ext_get_env(const char *, char *, size_t);
+int wasi_ext_environ(char *, size_t);
 int wasi_ext_getpid();
+int wasi_ext_getppid();
+int wasi_ext_getpgid(int);
+int wasi_ext_setpgid(int, int);
+int wasi_ext_getsid(int);
+int wasi_ext_setsid();
 #ifdef HTERM
 int wasi_ext_event_source_fd(uint32_t);
 int wasi_ext_attach_sigint(int32_t);
+int wasi_ext_attach_signal(int32_t, int);
 #endif
 int wasi_ext_clean_inodes();
+int wasi_ext_clean_inodes_report(int *);
 int wasi_ext_spawn(const char *, const char *const *, size_t,
                    const struct Env *, size_t, int, const struct Redirect *,
                    size_t, int *);
 int wasi_ext_kill(int, int);
+int wasi_ext_waitpid(int, int, int *);
+int wasi_ext_renice(int, int, int *);
+int wasi_ext_sigprocmask(int, uint32_t, uint32_t *);
 int wasi_ext_ioctl(int, unsigned int, void *);
+int wasi_ext_ioctl_buf(int, unsigned int, void *, size_t);
 int wasi_ext_fcntl(int, enum FcntlCommand, void *);
+int wasi_ext_pipe(int *, __wasi_fdflags_t);
+int wasi_ext_close_range(unsigned int, unsigned int, unsigned int);
+int wasi_ext_memfd_create(const char *, unsigned int);
+int wasi_ext_copy_file_range(int, int, size_t);
 int wasi_ext_mount(int, const char *, int, const char *, const char *, uint64_t,
                    const char *);
 int wasi_ext_umount(const char *);
 int wasi_ext_mknod(const char *, int);
+int wasi_ext_umask(int);
+int wasi_ext_getrlimit(int, struct Rlimit *);
+int wasi_ext_setrlimit(int, const struct Rlimit *);
+int wasi_ext_symlink(const char *, const char *);
+int wasi_ext_link(const char *, const char *);
+int wasi_ext_readlink(const char *, char *, size_t);
+int wasi_ext_realpath(const char *, char *, size_t);
+int wasi_ext_access(const char *, int);
+int wasi_ext_mkdir(const char *, int);
+int wasi_ext_rmdir(const char *);
+int wasi_ext_rename(const char *, const char *);
+int wasi_ext_chmod(const char *, int);
+int wasi_ext_fchmod(int, int);
+int wasi_ext_chown(const char *, int, int);
+int wasi_ext_fchown(int, int, int);
+int wasi_ext_truncate(const char *, uint64_t);
+int wasi_ext_flock(int, int);
+int wasi_ext_stat(const char *, int, struct Stat *);
+int wasi_ext_fstat(int, struct Stat *);
+#ifdef SOCKETS
+int wasi_ext_socket(int, int, int);
+int wasi_ext_bind(int, const char *);
+int wasi_ext_connect(int, const char *);
+int wasi_ext_listen(int, int);
+#endif
+int wasi_ext_statvfs(const char *, struct StatVfs *);
 
 #endif
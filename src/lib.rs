@@ -9,22 +9,30 @@ use std::collections::HashMap;
 use std::convert::AsRef;
 use std::convert::From;
 use std::env;
-use std::ffi::{c_int, c_uint, c_ulong, c_void, CString};
+use std::error;
+use std::ffi::{c_int, c_uint, c_ulong, c_void, CStr, CString, NulError, OsStr, OsString};
+use std::fmt;
 use std::fs;
 use std::io;
 use std::mem;
-use std::os::fd::AsRawFd;
 use std::os::fd::RawFd;
-use std::os::wasi::ffi::OsStrExt;
+use std::os::fd::{AsRawFd, IntoRawFd};
+#[cfg(not(target_os = "wasi"))]
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+#[cfg(target_os = "wasi")]
+use std::os::wasi::ffi::{OsStrExt, OsStringExt};
 use std::path::Path;
 use std::ptr;
 use std::str;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use bitflags::bitflags;
 
 mod wasi_ext_lib_generated;
 use wasi_ext_lib_generated::{
     RedirectType_APPEND, RedirectType_CLOSE, RedirectType_DUPLICATE, RedirectType_PIPEIN,
-    RedirectType_PIPEOUT, RedirectType_READ, RedirectType_READWRITE, RedirectType_WRITE,
-    Redirect_Data, Redirect_Data_Path,
+    RedirectType_PIPEOUT, RedirectType_READ, RedirectType_READWRITE, RedirectType_READWRITEAPPEND,
+    RedirectType_WRITE, Redirect_Data, Redirect_Data_Path,
 };
 
 #[cfg(feature = "hterm")]
@@ -33,6 +41,16 @@ pub use wasi_ext_lib_generated::WasiEvents;
 pub mod termios_generated;
 pub use termios_generated as termios;
 
+#[cfg(feature = "mock")]
+mod mock;
+#[cfg(feature = "mock")]
+use mock::wasi;
+#[cfg(all(feature = "mock", target_os = "wasi"))]
+compile_error!("the mock feature replaces WASI imports and only builds for native targets");
+
+#[cfg(test)]
+mod tests;
+
 // #[cfg(feature = "hterm")]
 // pub use wasi_ext_lib_generated::{
 //      WASI_EVENT_SIGINT, WASI_EVENTS_NUM
@@ -46,13 +64,17 @@ pub use termios_generated as termios;
 // from macros. These constants need to be hard-coded for now.
 // See https://github.com/rust-lang/rust-bindgen/issues/753
 #[cfg(feature = "hterm")]
-pub const WASI_EVENTS_NUM: usize = 2;
+pub const WASI_EVENTS_NUM: usize = 4;
 #[cfg(feature = "hterm")]
 pub const WASI_EVENTS_MASK_SIZE: usize = 4;
 #[cfg(feature = "hterm")]
 pub const WASI_EVENT_WINCH: WasiEvents = 1 << 0;
 #[cfg(feature = "hterm")]
 pub const WASI_EVENT_SIGINT: WasiEvents = 1 << 1;
+#[cfg(feature = "hterm")]
+pub const WASI_EVENT_SIGTERM: WasiEvents = 1 << 2;
+#[cfg(feature = "hterm")]
+pub const WASI_EVENT_SIGHUP: WasiEvents = 1 << 3;
 
 pub const WASI_EXT_FDFLAG_CTRL_BIT: wasi::Fdflags = 0x0020;
 pub const WASI_EXT_FDFLAG_MASK: wasi::Fdflags = 0xffc0;
@@ -69,41 +91,321 @@ pub const FIFOSCLOSERM: c_ulong = 1074003970;
 pub use wasi::SIGNAL_KILL;
 
 type ExitCode = i32;
-type Pid = i32;
+
+// Kept distinct from ExitCode so that swapped values do not type check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Pid(i32);
+
+impl Pid {
+    #[inline]
+    pub const fn from_raw(pid: i32) -> Self {
+        Pid(pid)
+    }
+
+    #[inline]
+    pub const fn into_raw(self) -> i32 {
+        self.0
+    }
+}
+
+impl fmt::Display for Pid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
 
 pub type Fd = wasi::Fd;
 
-#[derive(Debug)]
+// Foreground processes terminated by signal N exit with EXIT_SIGNAL_BASE + N
+pub const EXIT_SIGNAL_BASE: ExitCode = 128;
+
+// Highest signal number known to the wasi crate (SIGNAL_SYS)
+const WASI_SIGNAL_MAX: u8 = 30;
+
+// Highest errno value known to the wasi crate (ERRNO_NOTCAPABLE)
+const WASI_ERRNO_MAX: u16 = 76;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WasiExtError {
+    Errno(wasi::Errno),
+    Other(i32),
+}
+
+impl WasiExtError {
+    // Syscalls report failures as (possibly negated) errno values, callers
+    // are expected to pass the positive value here
+    pub fn from_raw(code: i32) -> Self {
+        match u16::try_from(code) {
+            // wasi::Errno is a transparent wrapper over u16 without a public
+            // constructor, the range check keeps the value a valid variant
+            Ok(n) if n <= WASI_ERRNO_MAX => {
+                WasiExtError::Errno(unsafe { mem::transmute::<u16, wasi::Errno>(n) })
+            }
+            _ => WasiExtError::Other(code),
+        }
+    }
+
+    pub fn raw(&self) -> i32 {
+        match self {
+            WasiExtError::Errno(e) => e.raw().into(),
+            WasiExtError::Other(n) => *n,
+        }
+    }
+}
+
+// errno left by the last failed libc call of the C library, ERRNO_SUCCESS if
+// there was none. Wrappers never need it, every binding reports failures
+// through its return value, see wasi_ext_lib.h for the conventions
+pub fn last_errno() -> wasi::Errno {
+    match WasiExtError::from_raw(unsafe { wasi_ext_lib_generated::wasi_ext_errno() }) {
+        WasiExtError::Errno(errno) => errno,
+        WasiExtError::Other(_) => wasi::ERRNO_IO,
+    }
+}
+
+impl From<wasi::Errno> for WasiExtError {
+    fn from(errno: wasi::Errno) -> Self {
+        WasiExtError::from_raw(errno.raw().into())
+    }
+}
+
+// Strings with interior NUL bytes cannot be passed to the kernel
+impl From<NulError> for WasiExtError {
+    fn from(_: NulError) -> Self {
+        WasiExtError::Errno(wasi::ERRNO_INVAL)
+    }
+}
+
+impl From<WasiExtError> for i32 {
+    fn from(err: WasiExtError) -> Self {
+        err.raw()
+    }
+}
+
+impl fmt::Display for WasiExtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WasiExtError::Errno(e) => write!(f, "E{}", e.name()),
+            WasiExtError::Other(n) => write!(f, "unknown error {n}"),
+        }
+    }
+}
+
+impl error::Error for WasiExtError {}
+
+fn signal_from_raw(signum: i32) -> Option<wasi::Signal> {
+    match u8::try_from(signum) {
+        // same as for wasi::Errno, the range check keeps the value valid
+        Ok(n) if n <= WASI_SIGNAL_MAX => Some(unsafe { mem::transmute::<u8, wasi::Signal>(n) }),
+        _ => None,
+    }
+}
+
+// Returns the signal that terminated a foreground process, if any
+pub fn exit_signal(exit_code: ExitCode) -> Option<wasi::Signal> {
+    if exit_code > EXIT_SIGNAL_BASE {
+        signal_from_raw(exit_code - EXIT_SIGNAL_BASE)
+    } else {
+        None
+    }
+}
+
+// Every signal known to the wasi crate, in numeric order. Signal 0 is not
+// included, it is only meaningful as a kill probe
+pub fn signals() -> impl Iterator<Item = wasi::Signal> {
+    (1..=WASI_SIGNAL_MAX as i32).filter_map(signal_from_raw)
+}
+
+// Bare name without the SIG prefix, e.g. "TERM"
+pub fn signal_name(signal: wasi::Signal) -> &'static str {
+    signal.name()
+}
+
+// Accepts "TERM", "SIGTERM" in any case, or the WASI signal number. Numbers
+// follow WASI numbering, which is not the same as Linux for every signal
+pub fn signal_from_name(name: &str) -> Option<wasi::Signal> {
+    if let Ok(signum) = name.parse::<i32>() {
+        return signal_from_raw(signum);
+    }
+    let bare = match name.get(..3) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("SIG") => &name[3..],
+        _ => name,
+    };
+    signals().find(|signal| signal.name().eq_ignore_ascii_case(bare))
+}
+
+// All variants taking a path except Read create the file if it is missing.
+// Only Write truncates it, Append and ReadWriteAppend start every write at
+// the end of the file and ReadWrite starts at offset 0 without truncating
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Redirect {
     Read(Fd, String),
     Write(Fd, String),
     Append(Fd, String),
     ReadWrite(Fd, String),
+    ReadWriteAppend(Fd, String),
     PipeIn(Fd),
     PipeOut(Fd),
     Duplicate { fd_src: Fd, fd_dst: Fd },
     Close(Fd),
 }
 
+impl Redirect {
+    // The fd of the process that gets replaced
+    fn target_fd(&self) -> Fd {
+        match self {
+            Redirect::Read(fd, _)
+            | Redirect::Write(fd, _)
+            | Redirect::Append(fd, _)
+            | Redirect::ReadWrite(fd, _)
+            | Redirect::ReadWriteAppend(fd, _)
+            | Redirect::Close(fd) => *fd,
+            Redirect::Duplicate { fd_dst, .. } => *fd_dst,
+            Redirect::PipeIn(_) => io::stdin().as_raw_fd() as Fd,
+            Redirect::PipeOut(_) => io::stdout().as_raw_fd() as Fd,
+        }
+    }
+}
+
+// Per stream setup for Command, like std::process::Stdio. Pipe takes the end
+// of an existing pipe that the child should use
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stdio {
+    Inherit,
+    Null,
+    Pipe(Fd),
+    // Makes the stream a copy of another fd of the parent
+    Fd(Fd),
+    // Input streams read from the file, output streams truncate it
+    File(String),
+    Close,
+}
+
+const DEV_NULL: &str = "/dev/null";
+
+impl Stdio {
+    // Redirect replacing `fd` in the child, None for Inherit
+    pub fn to_redirect(&self, fd: Fd) -> Option<Redirect> {
+        let is_input = fd == io::stdin().as_raw_fd() as Fd;
+        Some(match self {
+            Stdio::Inherit => return None,
+            Stdio::Null if is_input => Redirect::Read(fd, String::from(DEV_NULL)),
+            Stdio::Null => Redirect::Write(fd, String::from(DEV_NULL)),
+            Stdio::Pipe(pipe_fd) if is_input => Redirect::PipeIn(*pipe_fd),
+            Stdio::Pipe(pipe_fd) if fd == io::stdout().as_raw_fd() as Fd => {
+                Redirect::PipeOut(*pipe_fd)
+            }
+            Stdio::Pipe(src) | Stdio::Fd(src) => Redirect::Duplicate {
+                fd_src: *src,
+                fd_dst: fd,
+            },
+            Stdio::File(path) if is_input => Redirect::Read(fd, path.clone()),
+            Stdio::File(path) => Redirect::Write(fd, path.clone()),
+            Stdio::Close => Redirect::Close(fd),
+        })
+    }
+}
+
+// Parses a single shell redirection token like "2>&1", ">>log" or "<&-",
+// the fd defaults to 0 for input and to 1 for output redirections. "<>>" is
+// not standard shell syntax, it stands for ReadWriteAppend
+impl str::FromStr for Redirect {
+    type Err = WasiExtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = WasiExtError::Errno(wasi::ERRNO_INVAL);
+        let op_start = s.find(|c: char| !c.is_ascii_digit()).ok_or(invalid)?;
+        let fd = if op_start == 0 {
+            None
+        } else {
+            Some(s[..op_start].parse::<Fd>().map_err(|_| invalid)?)
+        };
+
+        let rest = &s[op_start..];
+        let (op, target) = ["<>>", "<>", ">>", ">&", "<&", ">", "<"]
+            .iter()
+            .find_map(|op| {
+                rest.strip_prefix(op)
+                    .map(|target| (*op, target.trim_start()))
+            })
+            .ok_or(invalid)?;
+        if target.is_empty() {
+            return Err(invalid);
+        }
+
+        let fd = fd.unwrap_or(if op.starts_with('<') { 0 } else { 1 });
+        let path = String::from(target);
+        match op {
+            "<>>" => Ok(Redirect::ReadWriteAppend(fd, path)),
+            "<>" => Ok(Redirect::ReadWrite(fd, path)),
+            ">>" => Ok(Redirect::Append(fd, path)),
+            ">" => Ok(Redirect::Write(fd, path)),
+            "<" => Ok(Redirect::Read(fd, path)),
+            _ if target == "-" => Ok(Redirect::Close(fd)),
+            _ => Ok(Redirect::Duplicate {
+                fd_src: target.parse::<Fd>().map_err(|_| invalid)?,
+                fd_dst: fd,
+            }),
+        }
+    }
+}
+
+// Pipe redirections have no syntax of their own, they are rendered as the
+// equivalent duplication of the pipe end onto stdin/stdout
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Redirect::Read(fd, path) => write!(f, "{fd}<{path}"),
+            Redirect::Write(fd, path) => write!(f, "{fd}>{path}"),
+            Redirect::Append(fd, path) => write!(f, "{fd}>>{path}"),
+            Redirect::ReadWrite(fd, path) => write!(f, "{fd}<>{path}"),
+            Redirect::ReadWriteAppend(fd, path) => write!(f, "{fd}<>>{path}"),
+            Redirect::PipeIn(fd_src) => write!(f, "0<&{fd_src}"),
+            Redirect::PipeOut(fd_src) => write!(f, "1>&{fd_src}"),
+            Redirect::Duplicate { fd_src, fd_dst } => write!(f, "{fd_dst}>&{fd_src}"),
+            Redirect::Close(fd) => write!(f, "{fd}>&-"),
+        }
+    }
+}
+
 #[repr(i32)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TcsetattrAction {
     TCSANOW = termios::TCSANOW as i32,
     TCSADRAIN = termios::TCSADRAIN as i32,
     TCSAFLUSH = termios::TCSAFLUSH as i32,
 }
 
+#[repr(i32)]
+pub enum FlushQueue {
+    TCIFLUSH = termios::TCIFLUSH as i32,
+    TCOFLUSH = termios::TCOFLUSH as i32,
+    TCIOFLUSH = termios::TCIOFLUSH as i32,
+}
+
+#[repr(i32)]
+pub enum FlowAction {
+    TCOOFF = termios::TCOOFF as i32,
+    TCOON = termios::TCOON as i32,
+    TCIOFF = termios::TCIOFF as i32,
+    TCION = termios::TCION as i32,
+}
+
 impl From<&Redirect> for wasi_ext_lib_generated::Redirect {
     fn from(redirect: &Redirect) -> Self {
         match redirect {
             Redirect::Read(fd, path)
             | Redirect::Write(fd, path)
             | Redirect::Append(fd, path)
-            | Redirect::ReadWrite(fd, path) => {
+            | Redirect::ReadWrite(fd, path)
+            | Redirect::ReadWriteAppend(fd, path) => {
                 let tag = match redirect {
                     Redirect::Read(_, _) => RedirectType_READ,
                     Redirect::Write(_, _) => RedirectType_WRITE,
                     Redirect::Append(_, _) => RedirectType_APPEND,
                     Redirect::ReadWrite(_, _) => RedirectType_READWRITE,
+                    Redirect::ReadWriteAppend(_, _) => RedirectType_READWRITEAPPEND,
                     _ => unreachable!(),
                 };
 
@@ -148,298 +450,3177 @@ impl From<&Redirect> for wasi_ext_lib_generated::Redirect {
     }
 }
 
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct WaitOptions: c_int {
+        const WNOHANG = wasi_ext_lib_generated::WNOHANG as c_int;
+        // also report children stopped by a signal
+        const WUNTRACED = wasi_ext_lib_generated::WUNTRACED as c_int;
+        // also report stopped children resumed by SIGCONT
+        const WCONTINUED = wasi_ext_lib_generated::WCONTINUED as c_int;
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct MountFlags: u64 {
+        const RDONLY = wasi_ext_lib_generated::MS_RDONLY as u64;
+        const NOSUID = wasi_ext_lib_generated::MS_NOSUID as u64;
+        const NODEV = wasi_ext_lib_generated::MS_NODEV as u64;
+        const NOEXEC = wasi_ext_lib_generated::MS_NOEXEC as u64;
+        const SYNCHRONOUS = wasi_ext_lib_generated::MS_SYNCHRONOUS as u64;
+        const REMOUNT = wasi_ext_lib_generated::MS_REMOUNT as u64;
+        const NOATIME = wasi_ext_lib_generated::MS_NOATIME as u64;
+    }
+}
+
+// Filesystem specific mount data, rendered as comma separated key[=value]
+// pairs when passed to the kernel. Mounting fails with EINVAL if a key or
+// value contains one of the separators or a key is empty
+#[derive(Debug, Clone, Default)]
+pub struct MountOptions {
+    opts: Vec<(String, Option<String>)>,
+}
+
+impl MountOptions {
+    pub fn new() -> Self {
+        MountOptions { opts: Vec::new() }
+    }
+
+    pub fn opt(&mut self, key: &str, val: &str) -> &mut Self {
+        self.opts.push((String::from(key), Some(String::from(val))));
+        self
+    }
+
+    pub fn flag(&mut self, key: &str) -> &mut Self {
+        self.opts.push((String::from(key), None));
+        self
+    }
+
+    // The rendered data, separators inside keys or values would silently
+    // split them into further options
+    fn to_data(&self) -> Result<String, WasiExtError> {
+        let is_plain = |s: &str| !s.contains([',', '=']);
+        let valid = self.opts.iter().all(|(key, val)| {
+            !key.is_empty() && is_plain(key) && val.as_deref().into_iter().all(is_plain)
+        });
+        if !valid {
+            return Err(WasiExtError::Errno(wasi::ERRNO_INVAL));
+        }
+        Ok(self.to_string())
+    }
+}
+
+impl fmt::Display for MountOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (key, val)) in self.opts.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            match val {
+                Some(v) => write!(f, "{key}={v}")?,
+                None => write!(f, "{key}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+bitflags! {
+    // Same values as F_OK, R_OK, W_OK and X_OK
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct AccessMode: c_int {
+        const EXISTS = 0;
+        const READ = 4;
+        const WRITE = 2;
+        const EXECUTE = 1;
+    }
+}
+
 pub enum FcntlCommand {
     // like F_DUPFD but it move fd insted of duplicating
     F_MVFD { min_fd_num: Fd },
     F_GETFD,
     F_SETFD { flags: wasi::Fdflags },
+    F_DUPFD { min_fd_num: Fd },
+    F_GETFL,
+    F_SETFL { flags: wasi::Fdflags },
+    // returns the pid holding a lock that conflicts with the given one, 0 if
+    // there is none. get_lock reports the whole conflicting lock
+    F_GETLK { lock: RecordLock },
+    F_SETLK { lock: RecordLock },
+    // like F_SETLK but waits for conflicting locks to be released
+    F_SETLKW { lock: RecordLock },
 }
 
-pub fn chdir<P: AsRef<Path>>(path: P) -> Result<(), ExitCode> {
-    if let Ok(canon) = fs::canonicalize(path.as_ref()) {
-        if let Err(e) = env::set_current_dir(canon.as_path()) {
-            return Err(e
-                .raw_os_error()
-                .unwrap_or_else(|| wasi::ERRNO_INVAL.raw().into()));
-        };
-        let pth = match CString::new(canon.as_os_str().as_bytes()) {
-            Ok(p) => p,
-            Err(_) => return Err(wasi::ERRNO_INVAL.raw().into()),
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockType {
+    Read,
+    Write,
+    Unlock,
+}
+
+// Byte-range lock taken with fcntl, len 0 extends the lock to the end of the
+// file. pid is ignored when locking and filled in by get_lock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordLock {
+    pub lock_type: LockType,
+    pub whence: Whence,
+    pub start: i64,
+    pub len: i64,
+    pub pid: Pid,
+}
+
+impl RecordLock {
+    pub fn new(lock_type: LockType, whence: Whence, start: i64, len: i64) -> Self {
+        RecordLock {
+            lock_type,
+            whence,
+            start,
+            len,
+            pid: Pid::from_raw(0),
+        }
+    }
+
+    fn to_raw(self) -> wasi_ext_lib_generated::Flock {
+        let lock_type = match self.lock_type {
+            LockType::Read => wasi_ext_lib_generated::F_RDLCK,
+            LockType::Write => wasi_ext_lib_generated::F_WRLCK,
+            LockType::Unlock => wasi_ext_lib_generated::F_UNLCK,
         };
-        match unsafe { wasi_ext_lib_generated::wasi_ext_chdir(pth.as_ptr()) } {
-            0 => Ok(()),
-            e => Err(e),
+        wasi_ext_lib_generated::Flock {
+            start: self.start,
+            len: self.len,
+            pid: self.pid.into_raw(),
+            type_: lock_type as i16,
+            whence: wasi::Whence::from(self.whence).raw() as i16,
         }
-    } else {
-        Err(wasi::ERRNO_INVAL.raw().into())
+    }
+
+    fn from_raw(raw: &wasi_ext_lib_generated::Flock) -> Result<Self, WasiExtError> {
+        let lock_type = match raw.type_ as u32 {
+            wasi_ext_lib_generated::F_RDLCK => LockType::Read,
+            wasi_ext_lib_generated::F_WRLCK => LockType::Write,
+            wasi_ext_lib_generated::F_UNLCK => LockType::Unlock,
+            _ => return Err(WasiExtError::Errno(wasi::ERRNO_INVAL)),
+        };
+        let whence = match raw.whence as u8 {
+            n if n == wasi::WHENCE_SET.raw() => Whence::Set,
+            n if n == wasi::WHENCE_CUR.raw() => Whence::Cur,
+            n if n == wasi::WHENCE_END.raw() => Whence::End,
+            _ => return Err(WasiExtError::Errno(wasi::ERRNO_INVAL)),
+        };
+        Ok(RecordLock {
+            lock_type,
+            whence,
+            start: raw.start,
+            len: raw.len,
+            pid: Pid::from_raw(raw.pid),
+        })
     }
 }
 
-pub fn getcwd() -> Result<String, ExitCode> {
-    const MAX_BUF_SIZE: usize = 65536;
-    let mut buf_size: usize = 256;
-    let mut buf = vec![0u8; buf_size];
-    while buf_size < MAX_BUF_SIZE {
-        match unsafe {
-            wasi_ext_lib_generated::wasi_ext_getcwd(buf.as_mut_ptr() as *mut i8, buf_size)
-        } {
-            0 => {
-                return Ok(String::from(
-                    str::from_utf8(&buf[..buf.iter().position(|&i| i == 0).unwrap()]).unwrap(),
-                ))
-            }
+// Under the mock std reports host errno values, which need translating
+fn io_error_to_wasi(e: io::Error) -> WasiExtError {
+    match e.raw_os_error() {
+        #[cfg(feature = "mock")]
+        Some(_) => WasiExtError::from_raw(mock::host_errno(&e)),
+        #[cfg(not(feature = "mock"))]
+        Some(errno) => WasiExtError::from_raw(errno),
+        None => WasiExtError::Errno(wasi::ERRNO_INVAL),
+    }
+}
+
+// Paths are passed as raw bytes, they do not have to be valid UTF-8
+pub fn chdir<P: AsRef<Path>>(path: P) -> Result<(), WasiExtError> {
+    let canon = fs::canonicalize(path.as_ref()).map_err(io_error_to_wasi)?;
+    env::set_current_dir(canon.as_path()).map_err(io_error_to_wasi)?;
+    let pth = CString::new(canon.as_os_str().as_bytes())?;
+    match unsafe { wasi_ext_lib_generated::wasi_ext_chdir(pth.as_ptr()) } {
+        0 => Ok(()),
+        e => Err(WasiExtError::from_raw(e)),
+    }
+}
+
+// The path is canonicalized before it is passed on, so this still allocates
+pub fn chdir_cstr(path: &CStr) -> Result<(), WasiExtError> {
+    chdir(OsStr::from_bytes(path.to_bytes()))
+}
+
+// Fails with ENOTDIR if the fd is not a directory. The kernel resolves the fd,
+// std is then pointed at the resulting cwd like chdir does
+pub fn fchdir(fd: Fd) -> Result<(), WasiExtError> {
+    match unsafe { wasi_ext_lib_generated::wasi_ext_fchdir(fd as c_int) } {
+        0 => {}
+        e => return Err(WasiExtError::from_raw(e)),
+    }
+    let cwd = getcwd_os()?;
+    env::set_current_dir(cwd).map_err(io_error_to_wasi)
+}
+
+// Calls the syscall with a buffer that is doubled on every ENOBUFS until
+// the output fits, returns the whole buffer
+fn grow_buf_until_fits<F>(syscall: F) -> Result<Vec<u8>, WasiExtError>
+where
+    F: FnMut(*mut i8, usize) -> c_int,
+{
+    let mut buf = Vec::new();
+    grow_buf_into(&mut buf, syscall)?;
+    Ok(buf)
+}
+
+const DEFAULT_INITIAL_BUF_SIZE: usize = 256;
+const DEFAULT_MAX_BUF_SIZE: usize = 65536;
+
+// Same as grow_buf_until_fits but reuses the allocation of the given buffer
+fn grow_buf_into<F>(buf: &mut Vec<u8>, syscall: F) -> Result<(), WasiExtError>
+where
+    F: FnMut(*mut i8, usize) -> c_int,
+{
+    let initial = buf
+        .capacity()
+        .clamp(DEFAULT_INITIAL_BUF_SIZE, DEFAULT_MAX_BUF_SIZE);
+    grow_buf_with_limits(buf, initial, DEFAULT_MAX_BUF_SIZE, syscall)
+}
+
+// Fails with ENAMETOOLONG once a buffer of max bytes is still too small
+fn grow_buf_with_limits<F>(
+    buf: &mut Vec<u8>,
+    initial: usize,
+    max: usize,
+    mut syscall: F,
+) -> Result<(), WasiExtError>
+where
+    F: FnMut(*mut i8, usize) -> c_int,
+{
+    let mut buf_size = initial;
+    buf.clear();
+    buf.resize(buf_size, 0u8);
+    loop {
+        match syscall(buf.as_mut_ptr() as *mut i8, buf_size) {
+            0 => return Ok(()),
             e => {
-                if e != wasi::ERRNO_NOBUFS.raw().into() {
-                    return Err(e);
+                if e != c_int::from(wasi::ERRNO_NOBUFS.raw()) {
+                    return Err(WasiExtError::from_raw(e));
                 };
             }
         };
-        buf_size *= 2;
+        if buf_size >= max {
+            return Err(WasiExtError::Errno(wasi::ERRNO_NAMETOOLONG));
+        }
+        buf_size = buf_size.saturating_mul(2).min(max);
         buf.resize(buf_size, 0u8);
     }
-    Err(wasi::ERRNO_NAMETOOLONG.raw().into())
 }
 
-pub fn isatty(fd: i32) -> Result<bool, ExitCode> {
+// A kernel filling the whole buffer may leave out the terminator, the whole
+// buffer is the output then
+fn truncate_at_nul(buf: &mut Vec<u8>) {
+    if let Some(len) = buf.iter().position(|&i| i == 0) {
+        buf.truncate(len);
+    }
+}
+
+// Like grow_buf_until_fits but returns the output up to its NUL terminator
+fn read_growing_buf<F>(syscall: F) -> Result<Vec<u8>, WasiExtError>
+where
+    F: FnMut(*mut i8, usize) -> c_int,
+{
+    let mut buf = grow_buf_until_fits(syscall)?;
+    truncate_at_nul(&mut buf);
+    Ok(buf)
+}
+
+pub fn getcwd() -> Result<String, WasiExtError> {
+    getcwd_with_capacity(DEFAULT_INITIAL_BUF_SIZE, DEFAULT_MAX_BUF_SIZE)
+}
+
+// The buffer starts at initial bytes and is doubled up to max bytes
+pub fn getcwd_with_capacity(initial: usize, max: usize) -> Result<String, WasiExtError> {
+    if initial == 0 || initial > max {
+        return Err(WasiExtError::Errno(wasi::ERRNO_INVAL));
+    }
+    let mut buf = Vec::new();
+    grow_buf_with_limits(&mut buf, initial, max, |buf, buf_len| unsafe {
+        wasi_ext_lib_generated::wasi_ext_getcwd(buf, buf_len)
+    })?;
+    truncate_at_nul(&mut buf);
+    String::from_utf8(buf).map_err(|_| WasiExtError::Errno(wasi::ERRNO_ILSEQ))
+}
+
+pub fn getcwd_os() -> Result<OsString, WasiExtError> {
+    let buf = read_growing_buf(|buf, buf_len| unsafe {
+        wasi_ext_lib_generated::wasi_ext_getcwd(buf, buf_len)
+    })?;
+    Ok(OsString::from_vec(buf))
+}
+
+// Reuses the buffer between calls, it is only reallocated when the path
+// does not fit in its current capacity
+pub fn getcwd_into(buf: &mut Vec<u8>) -> Result<&str, WasiExtError> {
+    grow_buf_into(buf, |buf, buf_len| unsafe {
+        wasi_ext_lib_generated::wasi_ext_getcwd(buf, buf_len)
+    })?;
+    truncate_at_nul(buf);
+    str::from_utf8(buf).map_err(|_| WasiExtError::Errno(wasi::ERRNO_ILSEQ))
+}
+
+// Kernels without a hostname fail with ENOSYS, no default name is made up
+pub fn gethostname() -> Result<String, WasiExtError> {
+    let buf = read_growing_buf(|buf, buf_len| unsafe {
+        wasi_ext_lib_generated::wasi_ext_gethostname(buf, buf_len)
+    })?;
+    String::from_utf8(buf).map_err(|_| WasiExtError::Errno(wasi::ERRNO_ILSEQ))
+}
+
+// Fails with EPERM if the process is not allowed to change the hostname
+pub fn sethostname(name: &str) -> Result<(), WasiExtError> {
+    let c_name = CString::new(name)?;
+    match unsafe { wasi_ext_lib_generated::wasi_ext_sethostname(c_name.as_ptr()) } {
+        0 => Ok(()),
+        e => Err(WasiExtError::from_raw(e)),
+    }
+}
+
+pub fn isatty(fd: i32) -> Result<bool, WasiExtError> {
     let result = unsafe { wasi_ext_lib_generated::wasi_ext_isatty(fd) };
     if result < 0 {
-        Err(-result)
+        Err(WasiExtError::from_raw(-result))
     } else {
         Ok(result == 1)
     }
 }
 
-pub fn set_env(key: &str, val: Option<&str>) -> Result<(), ExitCode> {
-    let c_key = CString::new(key).unwrap();
-    match if let Some(v) = val {
-        let c_val = CString::new(v).unwrap();
-        unsafe { wasi_ext_lib_generated::wasi_ext_set_env(c_key.as_ptr(), c_val.as_ptr()) }
-    } else {
-        unsafe { wasi_ext_lib_generated::wasi_ext_set_env(c_key.as_ptr(), ptr::null::<i8>()) }
-    } {
+// Path of the terminal device behind the fd, fds that are not terminals fail
+// with ENOTTY
+pub fn ttyname(fd: Fd) -> Result<String, WasiExtError> {
+    let buf = read_growing_buf(|buf, buf_len| unsafe {
+        wasi_ext_lib_generated::wasi_ext_ttyname(fd as c_int, buf, buf_len)
+    })?;
+    String::from_utf8(buf).map_err(|_| WasiExtError::Errno(wasi::ERRNO_ILSEQ))
+}
+
+// Errors are treated as the stream not being a terminal
+pub fn is_stdin_tty() -> bool {
+    isatty(0).unwrap_or(false)
+}
+
+pub fn is_stdout_tty() -> bool {
+    isatty(1).unwrap_or(false)
+}
+
+pub fn is_stderr_tty() -> bool {
+    isatty(2).unwrap_or(false)
+}
+
+pub fn set_env(key: &str, val: Option<&str>) -> Result<(), WasiExtError> {
+    let c_key = CString::new(key)?;
+    let c_val = val.map(CString::new).transpose()?;
+    set_env_cstr(&c_key, c_val.as_deref())
+}
+
+// *_cstr variants take already NUL terminated strings and skip the CString
+// allocation of their &str counterparts
+pub fn set_env_cstr(key: &CStr, val: Option<&CStr>) -> Result<(), WasiExtError> {
+    let c_val = val.map_or(ptr::null::<i8>(), |v| v.as_ptr());
+    match unsafe { wasi_ext_lib_generated::wasi_ext_set_env(key.as_ptr(), c_val) } {
         0 => Ok(()),
-        e => Err(e),
+        e => Err(WasiExtError::from_raw(e)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetEnvsError {
+    pub key: String,
+    pub error: WasiExtError,
+}
+
+impl fmt::Display for SetEnvsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.key, self.error)
+    }
+}
+
+impl error::Error for SetEnvsError {}
+
+impl From<SetEnvsError> for WasiExtError {
+    fn from(err: SetEnvsError) -> Self {
+        err.error
     }
 }
 
-pub fn getpid() -> Result<Pid, ExitCode> {
+// The kernel has no batched binding, variables are set one by one and the
+// first failure stops the batch, earlier variables stay set
+pub fn set_envs(vars: &[(&str, Option<&str>)]) -> Result<(), SetEnvsError> {
+    for (key, val) in vars {
+        set_env(key, *val).map_err(|error| SetEnvsError {
+            key: String::from(*key),
+            error,
+        })?;
+    }
+    Ok(())
+}
+
+// The kernel holds the authoritative environment, so this can observe
+// changes that are not yet visible through std::env. Unset variables are
+// reported by the kernel with ENOENT
+pub fn get_env(key: &str) -> Result<Option<String>, WasiExtError> {
+    let c_key = CString::new(key)?;
+    match read_growing_buf(|buf, buf_len| unsafe {
+        wasi_ext_lib_generated::wasi_ext_get_env(c_key.as_ptr(), buf, buf_len)
+    }) {
+        Ok(val) => match String::from_utf8(val) {
+            Ok(v) => Ok(Some(v)),
+            Err(_) => Err(WasiExtError::Errno(wasi::ERRNO_ILSEQ)),
+        },
+        Err(WasiExtError::Errno(wasi::ERRNO_NOENT)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Kernel view of the whole environment, the kernel writes NUL terminated
+// KEY=VALUE entries followed by an empty one
+pub fn environ() -> Result<Vec<(String, String)>, WasiExtError> {
+    let buf = grow_buf_until_fits(|buf, buf_len| unsafe {
+        wasi_ext_lib_generated::wasi_ext_environ(buf, buf_len)
+    })?;
+
+    let mut vars = Vec::new();
+    for entry in buf.split(|&i| i == 0).take_while(|entry| !entry.is_empty()) {
+        let entry = match str::from_utf8(entry) {
+            Ok(e) => e,
+            Err(_) => return Err(WasiExtError::Errno(wasi::ERRNO_ILSEQ)),
+        };
+        // entries without a separator are treated as empty variables
+        let (key, val) = entry.split_once('=').unwrap_or((entry, ""));
+        vars.push((String::from(key), String::from(val)));
+    }
+    Ok(vars)
+}
+
+pub fn getpid() -> Result<Pid, WasiExtError> {
     let result = unsafe { wasi_ext_lib_generated::wasi_ext_getpid() };
     if result < 0 {
-        Err(-result)
+        Err(WasiExtError::from_raw(-result))
     } else {
-        Ok(result)
+        Ok(Pid::from_raw(result))
     }
 }
 
-#[cfg(feature = "hterm")]
-pub fn event_source_fd(event_mask: WasiEvents) -> Result<RawFd, ExitCode> {
-    let result = unsafe { wasi_ext_lib_generated::wasi_ext_event_source_fd(event_mask) };
+pub fn getppid() -> Result<Pid, WasiExtError> {
+    let result = unsafe { wasi_ext_lib_generated::wasi_ext_getppid() };
     if result < 0 {
-        Err(-result)
+        Err(WasiExtError::from_raw(-result))
     } else {
-        Ok(result)
+        Ok(Pid::from_raw(result))
     }
 }
 
-#[cfg(feature = "hterm")]
-pub fn attach_sigint(fd: RawFd) -> Result<(), ExitCode> {
-    let result = unsafe { wasi_ext_lib_generated::wasi_ext_attach_sigint(fd) };
+// Pid 0 refers to the calling process
+pub fn getpgid(pid: Pid) -> Result<Pid, WasiExtError> {
+    let result = unsafe { wasi_ext_lib_generated::wasi_ext_getpgid(pid.into_raw()) };
     if result < 0 {
-        Err(-result)
+        Err(WasiExtError::from_raw(-result))
     } else {
-        Ok(())
+        Ok(Pid::from_raw(result))
     }
 }
 
-pub fn clean_inodes() -> Result<(), ExitCode> {
-    match unsafe { wasi_ext_lib_generated::wasi_ext_clean_inodes() } {
-        0 => Ok(()),
-        n => Err(n),
-    }
+pub fn getpgrp() -> Result<Pid, WasiExtError> {
+    getpgid(Pid::from_raw(0))
 }
 
-pub fn spawn(
-    path: &str,
-    args: &[&str],
-    env: &HashMap<String, String>,
-    background: bool,
-    redirects: &[Redirect],
-) -> Result<(ExitCode, Pid), ExitCode> {
-    let mut child_pid: Pid = -1;
-    let syscall_result = unsafe {
-        let cstring_args = args
-            .iter()
-            .map(|arg| CString::new(*arg).unwrap())
-            .collect::<Vec<CString>>();
+// Kernels without session support fail with ENOSYS
+pub fn getsid(pid: Pid) -> Result<Pid, WasiExtError> {
+    let result = unsafe { wasi_ext_lib_generated::wasi_ext_getsid(pid.into_raw()) };
+    if result < 0 {
+        Err(WasiExtError::from_raw(-result))
+    } else {
+        Ok(Pid::from_raw(result))
+    }
+}
 
-        let cstring_env = env
-            .iter()
-            .map(|(key, val)| {
-                (
-                    CString::new(&key[..]).unwrap(),
-                    CString::new(&val[..]).unwrap(),
-                )
-            })
-            .collect::<Vec<(CString, CString)>>();
-        let redirects_len = redirects.len();
-        let redirects_vec = redirects
-            .iter()
-            .map(wasi_ext_lib_generated::Redirect::from)
-            .collect::<Vec<wasi_ext_lib_generated::Redirect>>();
-        wasi_ext_lib_generated::wasi_ext_spawn(
-            CString::new(path).unwrap().as_c_str().as_ptr(),
-            cstring_args
-                .iter()
-                .map(|arg| arg.as_c_str().as_ptr())
-                .collect::<Vec<*const i8>>()
-                .as_ptr(),
-            args.len(),
-            cstring_env
-                .iter()
-                .map(|(key, val)| wasi_ext_lib_generated::Env {
-                    attrib: key.as_c_str().as_ptr(),
-                    val: val.as_c_str().as_ptr(),
-                })
-                .collect::<Vec<wasi_ext_lib_generated::Env>>()
-                .as_ptr(),
-            env.len(),
-            background as i32,
-            redirects_vec.as_ptr(),
-            redirects_len,
-            &mut child_pid,
-        )
-    };
-    if syscall_result < 0 {
-        Err(-syscall_result)
+// Returns the new session id, which equals the caller's pid. Process group
+// leaders cannot start a new session and get EPERM
+pub fn setsid() -> Result<Pid, WasiExtError> {
+    let result = unsafe { wasi_ext_lib_generated::wasi_ext_setsid() };
+    if result < 0 {
+        Err(WasiExtError::from_raw(-result))
     } else {
-        Ok((syscall_result, child_pid))
+        Ok(Pid::from_raw(result))
     }
 }
 
-pub fn kill(pid: Pid, signal: wasi::Signal) -> Result<(), ExitCode> {
-    let result = unsafe { wasi_ext_lib_generated::wasi_ext_kill(pid, signal.raw() as i32) };
+// Pid 0 refers to the calling process, pgid 0 makes it a group leader
+pub fn setpgid(pid: Pid, pgid: Pid) -> Result<(), WasiExtError> {
+    let result =
+        unsafe { wasi_ext_lib_generated::wasi_ext_setpgid(pid.into_raw(), pgid.into_raw()) };
     if result < 0 {
-        Err(-result)
+        Err(WasiExtError::from_raw(-result))
     } else {
         Ok(())
     }
 }
 
-pub fn ioctl<T>(fd: RawFd, command: c_ulong, arg: Option<&mut T>) -> Result<(), ExitCode> {
-    let result = if let Some(arg) = arg {
-        unsafe {
-            let arg_ptr: *mut c_void = arg as *mut T as *mut c_void;
-            wasi_ext_lib_generated::wasi_ext_ioctl(fd, command as c_uint, arg_ptr)
-        }
-    } else {
-        unsafe {
-            let null_ptr = ptr::null_mut::<T>() as *mut c_void;
-            wasi_ext_lib_generated::wasi_ext_ioctl(fd, command as c_uint, null_ptr)
-        }
-    };
+#[cfg(feature = "hterm")]
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct EventMask: WasiEvents {
+        const WINCH = WASI_EVENT_WINCH;
+        const SIGINT = WASI_EVENT_SIGINT;
+        const SIGTERM = WASI_EVENT_SIGTERM;
+        const SIGHUP = WASI_EVENT_SIGHUP;
+    }
+}
+
+#[cfg(feature = "hterm")]
+pub fn event_source_fd(event_mask: EventMask) -> Result<RawFd, WasiExtError> {
+    event_source_fd_raw(event_mask.bits())
+}
 
+// Passes the mask unchecked, for events not yet known to EventMask
+#[cfg(feature = "hterm")]
+pub fn event_source_fd_raw(event_mask: WasiEvents) -> Result<RawFd, WasiExtError> {
+    let result = unsafe { wasi_ext_lib_generated::wasi_ext_event_source_fd(event_mask) };
     if result < 0 {
-        Err(-result)
+        Err(WasiExtError::from_raw(-result))
     } else {
-        Ok(())
+        Ok(result)
     }
 }
-pub fn fcntl(fd: Fd, cmd: FcntlCommand) -> Result<i32, ExitCode> {
-    let result = match cmd {
-        FcntlCommand::F_MVFD { min_fd_num } => unsafe {
-            let mut min_fd = min_fd_num;
-            wasi_ext_lib_generated::wasi_ext_fcntl(
-                fd as c_int,
-                wasi_ext_lib_generated::FcntlCommand_F_MVFD,
-                (&mut min_fd as *mut u32) as *mut c_void,
-            )
-        },
-        FcntlCommand::F_GETFD => unsafe {
-            let null_ptr = ptr::null_mut::<c_void>();
-            wasi_ext_lib_generated::wasi_ext_fcntl(
-                fd as c_int,
-                wasi_ext_lib_generated::FcntlCommand_F_GETFD,
-                null_ptr,
-            )
+
+#[cfg(feature = "hterm")]
+pub fn attach_sigint(fd: RawFd) -> Result<(), WasiExtError> {
+    attach_signal(fd, wasi::SIGNAL_INT)
+}
+
+// Routes a signal sent to this process to an event fd instead of its default
+// action, the fd must have been created with the matching EventMask bit. The
+// kernel delivers SIGINT (Ctrl-C in hterm), SIGTERM and SIGHUP this way,
+// SIGWINCH needs no attaching as WINCH events are always reported. Other
+// signals fail with EINVAL, kernels without attach_signal fail with ENOSYS for
+// anything but SIGINT
+#[cfg(feature = "hterm")]
+pub fn attach_signal(fd: RawFd, signal: wasi::Signal) -> Result<(), WasiExtError> {
+    let result = match signal {
+        // older kernels only know the dedicated syscall
+        wasi::SIGNAL_INT => unsafe { wasi_ext_lib_generated::wasi_ext_attach_sigint(fd) },
+        wasi::SIGNAL_TERM | wasi::SIGNAL_HUP => unsafe {
+            wasi_ext_lib_generated::wasi_ext_attach_signal(fd, signal.raw() as c_int)
         },
-        FcntlCommand::F_SETFD { flags } => unsafe {
-            let mut flags = flags;
-            wasi_ext_lib_generated::wasi_ext_fcntl(
-                fd as c_int,
-                wasi_ext_lib_generated::FcntlCommand_F_SETFD,
-                (&mut flags as *mut wasi::Fdflags) as *mut c_void,
+        wasi::SIGNAL_WINCH => 0,
+        _ => return Err(WasiExtError::Errno(wasi::ERRNO_INVAL)),
+    };
+    if result < 0 {
+        Err(WasiExtError::from_raw(-result))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "hterm")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Winch,
+    Sigint,
+    Sigterm,
+    Sighup,
+}
+
+#[cfg(feature = "hterm")]
+impl Event {
+    pub fn signal(&self) -> wasi::Signal {
+        match self {
+            Event::Winch => wasi::SIGNAL_WINCH,
+            Event::Sigint => wasi::SIGNAL_INT,
+            Event::Sigterm => wasi::SIGNAL_TERM,
+            Event::Sighup => wasi::SIGNAL_HUP,
+        }
+    }
+}
+
+// Decodes event masks read from an event_source_fd, the fd is not closed on
+// drop. Iteration ends when the fd reaches EOF
+#[cfg(feature = "hterm")]
+pub struct EventStream {
+    fd: RawFd,
+    pending: WasiEvents,
+}
+
+#[cfg(feature = "hterm")]
+impl EventStream {
+    pub fn new(fd: RawFd) -> Self {
+        EventStream { fd, pending: 0 }
+    }
+}
+
+#[cfg(feature = "hterm")]
+impl AsRawFd for EventStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+#[cfg(feature = "hterm")]
+impl Iterator for EventStream {
+    type Item = Result<Event, WasiExtError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending == 0 {
+            let mut buf = [0u8; WASI_EVENTS_MASK_SIZE];
+            let mut read = 0;
+            while read < WASI_EVENTS_MASK_SIZE {
+                let iovec = wasi::Iovec {
+                    buf: buf[read..].as_mut_ptr(),
+                    buf_len: WASI_EVENTS_MASK_SIZE - read,
+                };
+                match unsafe { wasi::fd_read(self.fd as Fd, &[iovec]) } {
+                    Ok(0) => return None,
+                    Ok(n) => read += n,
+                    Err(e) => return Some(Err(WasiExtError::from(e))),
+                }
+            }
+            // ignore bits of events unknown to this version of the library
+            self.pending = WasiEvents::from_le_bytes(buf) & ((1 << WASI_EVENTS_NUM) - 1);
+        }
+
+        // report events one by one, lowest bit first
+        let event = self.pending & self.pending.wrapping_neg();
+        self.pending &= !event;
+        Some(Ok(match event {
+            WASI_EVENT_WINCH => Event::Winch,
+            WASI_EVENT_SIGINT => Event::Sigint,
+            WASI_EVENT_SIGTERM => Event::Sigterm,
+            _ => Event::Sighup,
+        }))
+    }
+}
+
+pub fn clean_inodes() -> Result<(), WasiExtError> {
+    match unsafe { wasi_ext_lib_generated::wasi_ext_clean_inodes() } {
+        0 => Ok(()),
+        n => Err(WasiExtError::from_raw(n)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CleanReport {
+    // None if the kernel does not report how many inodes it reclaimed
+    pub reclaimed: Option<u32>,
+}
+
+pub fn clean_inodes_report() -> Result<CleanReport, WasiExtError> {
+    let mut reclaimed: c_int = -1;
+    match unsafe { wasi_ext_lib_generated::wasi_ext_clean_inodes_report(&mut reclaimed) } {
+        0 => Ok(CleanReport {
+            reclaimed: u32::try_from(reclaimed).ok(),
+        }),
+        n => Err(WasiExtError::from_raw(n)),
+    }
+}
+
+#[derive(Clone)]
+pub struct Command {
+    path: String,
+    args: Vec<String>,
+    env: HashMap<String, String>,
+    background: bool,
+    redirects: Vec<Redirect>,
+    validate_redirects: bool,
+}
+
+impl Command {
+    pub fn new(path: &str) -> Self {
+        Command {
+            path: String::from(path),
+            args: Vec::new(),
+            env: HashMap::new(),
+            background: false,
+            redirects: Vec::new(),
+            validate_redirects: false,
+        }
+    }
+
+    pub fn arg(&mut self, arg: &str) -> &mut Self {
+        self.args.push(String::from(arg));
+        self
+    }
+
+    pub fn args<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.args
+            .extend(args.into_iter().map(|arg| String::from(arg.as_ref())));
+        self
+    }
+
+    pub fn env(&mut self, key: &str, val: &str) -> &mut Self {
+        self.env.insert(String::from(key), String::from(val));
+        self
+    }
+
+    pub fn envs<I, K, V>(&mut self, vars: I) -> &mut Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        self.env.extend(
+            vars.into_iter()
+                .map(|(key, val)| (String::from(key.as_ref()), String::from(val.as_ref()))),
+        );
+        self
+    }
+
+    pub fn background(&mut self, background: bool) -> &mut Self {
+        self.background = background;
+        self
+    }
+
+    pub fn redirect(&mut self, redirect: Redirect) -> &mut Self {
+        self.redirects.push(redirect);
+        self
+    }
+
+    // Replaces earlier redirections of the same stream
+    fn stdio(&mut self, fd: Fd, stdio: Stdio) -> &mut Self {
+        self.redirects.retain(|redirect| redirect.target_fd() != fd);
+        self.redirects.extend(stdio.to_redirect(fd));
+        self
+    }
+
+    pub fn stdin(&mut self, stdin: Stdio) -> &mut Self {
+        self.stdio(io::stdin().as_raw_fd() as Fd, stdin)
+    }
+
+    pub fn stdout(&mut self, stdout: Stdio) -> &mut Self {
+        self.stdio(io::stdout().as_raw_fd() as Fd, stdout)
+    }
+
+    pub fn stderr(&mut self, stderr: Stdio) -> &mut Self {
+        self.stdio(io::stderr().as_raw_fd() as Fd, stderr)
+    }
+
+    pub fn get_redirects(&self) -> &[Redirect] {
+        &self.redirects
+    }
+
+    // Runs validate_redirects before spawning, off by default
+    pub fn validate_redirects(&mut self, validate: bool) -> &mut Self {
+        self.validate_redirects = validate;
+        self
+    }
+
+    // For foreground processes the exit code is the final status of the
+    // child (0-255, see exit_signal for processes killed by a signal) and
+    // the pid is no longer valid. For background processes the exit code is
+    // not meaningful and the pid can be passed to waitpid
+    pub fn spawn(&self) -> Result<(ExitCode, Pid), WasiExtError> {
+        if self.validate_redirects {
+            validate_redirects(&self.redirects)?;
+        }
+        let mut child_pid: i32 = -1;
+
+        // Every vector below backs a pointer handed to the kernel, so all of
+        // them have to stay alive until wasi_ext_spawn returns
+        let c_path = CString::new(&self.path[..])?;
+        let cstring_args = self
+            .args
+            .iter()
+            .map(|arg| CString::new(&arg[..]))
+            .collect::<Result<Vec<CString>, NulError>>()?;
+        let args_vec = cstring_args
+            .iter()
+            .map(|arg| arg.as_c_str().as_ptr())
+            .collect::<Vec<*const i8>>();
+
+        let cstring_env = self
+            .env
+            .iter()
+            .map(|(key, val)| Ok((CString::new(&key[..])?, CString::new(&val[..])?)))
+            .collect::<Result<Vec<(CString, CString)>, NulError>>()?;
+        let env_vec = cstring_env
+            .iter()
+            .map(|(key, val)| wasi_ext_lib_generated::Env {
+                attrib: key.as_c_str().as_ptr(),
+                val: val.as_c_str().as_ptr(),
+            })
+            .collect::<Vec<wasi_ext_lib_generated::Env>>();
+
+        let redirects_vec = self
+            .redirects
+            .iter()
+            .map(wasi_ext_lib_generated::Redirect::from)
+            .collect::<Vec<wasi_ext_lib_generated::Redirect>>();
+
+        let syscall_result = unsafe {
+            wasi_ext_lib_generated::wasi_ext_spawn(
+                c_path.as_ptr(),
+                args_vec.as_ptr(),
+                args_vec.len(),
+                env_vec.as_ptr(),
+                env_vec.len(),
+                self.background as i32,
+                redirects_vec.as_ptr(),
+                redirects_vec.len(),
+                &mut child_pid,
             )
-        },
+        };
+        if syscall_result < 0 {
+            Err(WasiExtError::from_raw(-syscall_result))
+        } else {
+            Ok((syscall_result, Pid::from_raw(child_pid)))
+        }
+    }
+
+    // Always spawns in the background, regardless of the background flag
+    pub fn spawn_child(&self) -> Result<Child, WasiExtError> {
+        let mut command = self.clone();
+        command.background(true);
+        command.spawn().map(|(_, pid)| Child::from_pid(pid))
+    }
+
+    // Runs the command in the background with stdout and stderr captured and
+    // waits for it, stdin stays as configured. Both pipes are drained together
+    // so a child filling one of them cannot block
+    pub fn output(&self) -> Result<Output, WasiExtError> {
+        let (out_read, out_write) = pipe_with_flags(WASI_EXT_FDFLAG_CLOEXEC)?;
+        let (err_read, err_write) = match pipe_with_flags(WASI_EXT_FDFLAG_CLOEXEC) {
+            Ok(fds) => fds,
+            Err(e) => {
+                let _ = close(out_read);
+                let _ = close(out_write);
+                return Err(e);
+            }
+        };
+
+        let mut command = self.clone();
+        command
+            .stdout(Stdio::Pipe(out_write))
+            .stderr(Stdio::Pipe(err_write));
+        let child = command.spawn_child();
+        // the child holds its own copies, EOF only comes once these are closed
+        let _ = close(out_write);
+        let _ = close(err_write);
+
+        let result = child.and_then(|mut child| {
+            let pipes = read_pipes([out_read, err_read]);
+            // the child has to be reaped on errors too, it is killed first as
+            // it may be blocked on the pipes that are no longer drained. A
+            // signal interrupting the wait does not stop it
+            if pipes.is_err() {
+                let _ = child.kill(wasi::SIGNAL_KILL);
+            }
+            let status = loop {
+                match child.wait() {
+                    Err(WasiExtError::Errno(wasi::ERRNO_INTR)) => continue,
+                    status => break status,
+                }
+            };
+            let [stdout, stderr] = pipes?;
+            Ok(Output {
+                status: status?,
+                stdout,
+                stderr,
+            })
+        });
+        let _ = close(out_read);
+        let _ = close(err_read);
+        result
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Output {
+    pub status: WaitStatus,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+// Reads every pipe until EOF, whichever has data first is read first
+fn read_pipes<const N: usize>(fds: [Fd; N]) -> Result<[Vec<u8>; N], WasiExtError> {
+    let mut bufs: [Vec<u8>; N] = std::array::from_fn(|_| Vec::new());
+    let mut open = [true; N];
+    let mut chunk = [0u8; DEFAULT_INITIAL_BUF_SIZE * 16];
+
+    while open.iter().any(|is_open| *is_open) {
+        let watched = (0..N)
+            .filter(|i| open[*i])
+            .map(|i| (fds[i], PollFlags::READABLE))
+            .collect::<Vec<(Fd, PollFlags)>>();
+        for ready in poll(&watched, None)? {
+            if let Some(e) = ready.error {
+                return Err(e);
+            }
+            let Some(i) = fds.iter().position(|fd| *fd == ready.fd) else {
+                continue;
+            };
+            let iovec = wasi::Iovec {
+                buf: chunk.as_mut_ptr(),
+                buf_len: chunk.len(),
+            };
+            match unsafe { wasi::fd_read(fds[i], &[iovec]) }? {
+                0 => open[i] = false,
+                n => bufs[i].extend_from_slice(&chunk[..n]),
+            }
+        }
+    }
+    Ok(bufs)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedirectError {
+    // position of the offending redirect in the checked slice
+    pub index: usize,
+    pub error: WasiExtError,
+}
+
+impl fmt::Display for RedirectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "redirect {}: {}", self.index, self.error)
+    }
+}
+
+impl error::Error for RedirectError {}
+
+impl From<RedirectError> for WasiExtError {
+    fn from(err: RedirectError) -> Self {
+        err.error
+    }
+}
+
+// Checks redirects the way the kernel applies them, in order: source fds have
+// to be open in this process or set up by an earlier redirect and not closed
+// since, paths must be non-empty without NUL bytes and Read needs an existing
+// file. Passing the check does not rule out other failures at spawn time
+pub fn validate_redirects(redirects: &[Redirect]) -> Result<(), RedirectError> {
+    // fds set up (true) or closed (false) by the redirects checked so far
+    let mut changed: HashMap<Fd, bool> = HashMap::new();
+
+    for (index, redirect) in redirects.iter().enumerate() {
+        let fail = |error| RedirectError { index, error };
+        match redirect {
+            Redirect::Read(_, path)
+            | Redirect::Write(_, path)
+            | Redirect::Append(_, path)
+            | Redirect::ReadWrite(_, path)
+            | Redirect::ReadWriteAppend(_, path) => {
+                if path.is_empty() || path.contains('\0') {
+                    return Err(fail(WasiExtError::Errno(wasi::ERRNO_INVAL)));
+                }
+                if let Redirect::Read(_, _) = redirect {
+                    stat(path).map_err(fail)?;
+                }
+            }
+            Redirect::PipeIn(fd_src)
+            | Redirect::PipeOut(fd_src)
+            | Redirect::Duplicate { fd_src, .. } => {
+                let open = match changed.get(fd_src) {
+                    Some(open) => *open,
+                    None => unsafe { wasi::fd_fdstat_get(*fd_src) }.is_ok(),
+                };
+                if !open {
+                    return Err(fail(WasiExtError::Errno(wasi::ERRNO_BADF)));
+                }
+            }
+            Redirect::Close(_) => {}
+        }
+        changed.insert(
+            redirect.target_fd(),
+            !matches!(redirect, Redirect::Close(_)),
+        );
+    }
+    Ok(())
+}
+
+fn command_from_parts<A, E, K, V>(
+    path: &str,
+    args: A,
+    env: E,
+    background: bool,
+    redirects: &[Redirect],
+) -> Command
+where
+    A: IntoIterator,
+    A::Item: AsRef<str>,
+    E: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    let mut command = Command::new(path);
+    command.args(args).envs(env).background(background);
+    for redirect in redirects {
+        command.redirect(redirect.clone());
+    }
+    command
+}
+
+// Runs the process to completion and returns its exit code, a process
+// terminated by signal N returns EXIT_SIGNAL_BASE + N
+pub fn spawn_foreground<A, E, K, V>(
+    path: &str,
+    args: A,
+    env: E,
+    redirects: &[Redirect],
+) -> Result<ExitCode, WasiExtError>
+where
+    A: IntoIterator,
+    A::Item: AsRef<str>,
+    E: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    command_from_parts(path, args, env, false, redirects)
+        .spawn()
+        .map(|(exit_code, _)| exit_code)
+}
+
+// Starts the process without waiting for it and returns its pid, the exit
+// code can be collected later with waitpid
+pub fn spawn_background<A, E, K, V>(
+    path: &str,
+    args: A,
+    env: E,
+    redirects: &[Redirect],
+) -> Result<Pid, WasiExtError>
+where
+    A: IntoIterator,
+    A::Item: AsRef<str>,
+    E: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    command_from_parts(path, args, env, true, redirects)
+        .spawn()
+        .map(|(_, child_pid)| child_pid)
+}
+
+// Captures stdout and stderr of the process, see Command::output
+pub fn output<A, E, K, V>(path: &str, args: A, env: E) -> Result<Output, WasiExtError>
+where
+    A: IntoIterator,
+    A::Item: AsRef<str>,
+    E: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    command_from_parts(path, args, env, true, &[]).output()
+}
+
+#[deprecated(note = "use spawn_foreground or spawn_background instead")]
+pub fn spawn<A, E, K, V>(
+    path: &str,
+    args: A,
+    env: E,
+    background: bool,
+    redirects: &[Redirect],
+) -> Result<(ExitCode, Pid), WasiExtError>
+where
+    A: IntoIterator,
+    A::Item: AsRef<str>,
+    E: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    command_from_parts(path, args, env, background, redirects).spawn()
+}
+
+// Negative pid sends the signal to every member of the process group -pid
+pub fn kill(pid: Pid, signal: wasi::Signal) -> Result<(), WasiExtError> {
+    let result =
+        unsafe { wasi_ext_lib_generated::wasi_ext_kill(pid.into_raw(), signal.raw() as i32) };
+    if result < 0 {
+        Err(WasiExtError::from_raw(-result))
+    } else {
+        Ok(())
+    }
+}
+
+// For signal numbers parsed from user input, prefer kill with wasi::Signal
+pub fn kill_raw(pid: Pid, signum: i32) -> Result<(), WasiExtError> {
+    match signal_from_raw(signum) {
+        Some(signal) => kill(pid, signal),
+        None => Err(WasiExtError::Errno(wasi::ERRNO_INVAL)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitStatus {
+    Exited(ExitCode),
+    Signaled(wasi::Signal),
+    // only reported with WUNTRACED
+    Stopped(wasi::Signal),
+    // only reported with WCONTINUED
+    Continued,
+}
+
+impl WaitStatus {
+    // Exit codes above EXIT_SIGNAL_BASE are taken as a signal, see exit_signal
+    pub fn from_raw(status: ExitCode) -> Self {
+        const STOPPED_MARK: ExitCode = wasi_ext_lib_generated::WSTOPPED_MARK as ExitCode;
+        const CONTINUED: ExitCode = wasi_ext_lib_generated::WCONTINUED_STATUS as ExitCode;
+
+        if status == CONTINUED {
+            return WaitStatus::Continued;
+        }
+        if status > 0xff && status & 0xff == STOPPED_MARK {
+            if let Some(signal) = signal_from_raw(status >> 8) {
+                return WaitStatus::Stopped(signal);
+            }
+        }
+        match exit_signal(status) {
+            Some(signal) => WaitStatus::Signaled(signal),
+            None => WaitStatus::Exited(status),
+        }
+    }
+}
+
+// Pass -1 as pid to wait for any child. With WNOHANG set, Ok((0, _)) means
+// no child has changed state yet, the status is meaningless then
+pub fn waitpid(pid: Pid, options: WaitOptions) -> Result<(Pid, WaitStatus), WasiExtError> {
+    let mut status: ExitCode = -1;
+    let result = unsafe {
+        wasi_ext_lib_generated::wasi_ext_waitpid(pid.into_raw(), options.bits(), &mut status)
+    };
+    if result < 0 {
+        Err(WasiExtError::from_raw(-result))
+    } else {
+        Ok((Pid::from_raw(result), WaitStatus::from_raw(status)))
+    }
+}
+
+// Owns a background process, the wait status is cached once it is reaped.
+// Dropping the handle never blocks, with reap_on_drop set it reaps the process
+// only if it has already exited
+pub struct Child {
+    pid: Pid,
+    status: Option<WaitStatus>,
+    reap_on_drop: bool,
+}
+
+impl Child {
+    pub fn from_pid(pid: Pid) -> Self {
+        Child {
+            pid,
+            status: None,
+            reap_on_drop: false,
+        }
+    }
+
+    pub fn id(&self) -> Pid {
+        self.pid
+    }
+
+    pub fn reap_on_drop(&mut self, reap: bool) -> &mut Self {
+        self.reap_on_drop = reap;
+        self
+    }
+
+    // Only reports an exit or a termination by signal, see waitpid for
+    // stopped and continued processes
+    pub fn wait(&mut self) -> Result<WaitStatus, WasiExtError> {
+        if let Some(status) = self.status {
+            return Ok(status);
+        }
+        let (_, status) = waitpid(self.pid, WaitOptions::empty())?;
+        self.status = Some(status);
+        Ok(status)
+    }
+
+    pub fn try_wait(&mut self) -> Result<Option<WaitStatus>, WasiExtError> {
+        if self.status.is_some() {
+            return Ok(self.status);
+        }
+        match waitpid(self.pid, WaitOptions::WNOHANG)? {
+            (pid, _) if pid.into_raw() == 0 => Ok(None),
+            (_, status) => {
+                self.status = Some(status);
+                Ok(self.status)
+            }
+        }
+    }
+
+    // Signalling a process that has already been reaped is a no-op, its pid
+    // may have been reused
+    pub fn kill(&mut self, signal: wasi::Signal) -> Result<(), WasiExtError> {
+        if self.status.is_some() {
+            return Ok(());
+        }
+        kill(self.pid, signal)
+    }
+}
+
+impl Drop for Child {
+    fn drop(&mut self) {
+        if self.reap_on_drop {
+            let _ = self.try_wait();
+        }
+    }
+}
+
+// Adds delta to the nice value of the process and returns the new value.
+// Nice values range from -20 (highest priority) to 19 (lowest), results
+// outside of this range are clamped by the kernel. Kernels without a
+// scheduler knob fail with ENOSYS
+pub fn renice(pid: Pid, delta: i32) -> Result<i32, WasiExtError> {
+    let mut priority: i32 = 0;
+    let result =
+        unsafe { wasi_ext_lib_generated::wasi_ext_renice(pid.into_raw(), delta, &mut priority) };
+    if result < 0 {
+        Err(WasiExtError::from_raw(-result))
+    } else {
+        Ok(priority)
+    }
+}
+
+// Bit N of the set stands for signal N
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SignalSet(u32);
+
+impl SignalSet {
+    pub fn empty() -> Self {
+        SignalSet(0)
+    }
+
+    pub fn add(&mut self, signal: wasi::Signal) -> &mut Self {
+        self.0 |= 1 << signal.raw();
+        self
+    }
+
+    pub fn remove(&mut self, signal: wasi::Signal) -> &mut Self {
+        self.0 &= !(1 << signal.raw());
+        self
+    }
+
+    pub fn contains(&self, signal: wasi::Signal) -> bool {
+        self.0 & (1 << signal.raw()) != 0
+    }
+
+    pub fn raw(&self) -> u32 {
+        self.0
+    }
+}
+
+impl FromIterator<wasi::Signal> for SignalSet {
+    fn from_iter<I: IntoIterator<Item = wasi::Signal>>(iter: I) -> Self {
+        let mut set = SignalSet::empty();
+        for signal in iter {
+            set.add(signal);
+        }
+        set
+    }
+}
+
+fn sigprocmask(how: u32, set: SignalSet) -> Result<SignalSet, WasiExtError> {
+    let mut oldset: u32 = 0;
+    let result = unsafe {
+        wasi_ext_lib_generated::wasi_ext_sigprocmask(how as c_int, set.raw(), &mut oldset)
     };
+    if result < 0 {
+        Err(WasiExtError::from_raw(-result))
+    } else {
+        Ok(SignalSet(oldset))
+    }
+}
+
+// Both return the previous mask, kernels without signal masking fail with
+// ENOSYS
+pub fn block_signals(set: SignalSet) -> Result<SignalSet, WasiExtError> {
+    sigprocmask(wasi_ext_lib_generated::SIG_BLOCK, set)
+}
+
+pub fn unblock_signals(set: SignalSet) -> Result<SignalSet, WasiExtError> {
+    sigprocmask(wasi_ext_lib_generated::SIG_UNBLOCK, set)
+}
+
+pub fn ioctl<T>(fd: RawFd, command: c_ulong, arg: Option<&mut T>) -> Result<(), WasiExtError> {
+    let result = if let Some(arg) = arg {
+        unsafe {
+            let arg_ptr: *mut c_void = arg as *mut T as *mut c_void;
+            wasi_ext_lib_generated::wasi_ext_ioctl(fd, command as c_uint, arg_ptr)
+        }
+    } else {
+        unsafe {
+            let null_ptr = ptr::null_mut::<T>() as *mut c_void;
+            wasi_ext_lib_generated::wasi_ext_ioctl(fd, command as c_uint, null_ptr)
+        }
+    };
+
+    if result < 0 {
+        Err(WasiExtError::from_raw(-result))
+    } else {
+        Ok(())
+    }
+}
+
+// Unlike ioctl, the payload size is taken from the buffer rather than
+// from the command, returns the number of bytes filled by the kernel
+pub fn ioctl_buf(fd: RawFd, command: c_ulong, buf: &mut [u8]) -> Result<usize, WasiExtError> {
+    let result = unsafe {
+        wasi_ext_lib_generated::wasi_ext_ioctl_buf(
+            fd,
+            command as c_uint,
+            buf.as_mut_ptr() as *mut c_void,
+            buf.len(),
+        )
+    };
+
+    if result < 0 {
+        Err(WasiExtError::from_raw(-result))
+    } else {
+        Ok(result as usize)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoctlSupport {
+    Supported,
+    Unsupported,
+}
+
+// Devices fail commands they do not know with ENOTTY, which is reported as
+// Unsupported so capability probes do not have to treat it as an error. Any
+// other failure is still an error
+pub fn ioctl_checked<T>(
+    fd: RawFd,
+    command: c_ulong,
+    arg: Option<&mut T>,
+) -> Result<IoctlSupport, WasiExtError> {
+    match ioctl(fd, command, arg) {
+        Ok(()) => Ok(IoctlSupport::Supported),
+        Err(WasiExtError::Errno(wasi::ERRNO_NOTTY)) => Ok(IoctlSupport::Unsupported),
+        Err(e) => Err(e),
+    }
+}
+
+// The kernel has no by-value ioctl arguments, every argument travels through a
+// buffer whose size is encoded in the command. Commands built with a size of 4
+// (FIFOS*, WGETGS, TCSBRK, TCXONC, TCFLSH) carry an int: `arg` is copied in and
+// whatever the kernel leaves in that int is returned. Commands with other
+// sizes, e.g. TIOCGWINSZ or TCGETS, pass a struct by pointer and need ioctl
+pub fn ioctl_int(fd: RawFd, command: c_ulong, arg: i32) -> Result<i32, WasiExtError> {
+    let size = (command & wasi_ext_lib_generated::_IOS_MASK as c_ulong)
+        >> wasi_ext_lib_generated::_IOS_OFF;
+    if size != 0 && size != mem::size_of::<i32>() as c_ulong {
+        return Err(WasiExtError::Errno(wasi::ERRNO_INVAL));
+    }
+    let mut value = arg;
+    ioctl(fd, command, Some(&mut value))?;
+    Ok(value)
+}
+
+// FIFO ioctls take a single int argument (encoded in the command size)
+pub fn fifo_set_kernel_write(fd: RawFd, value: c_int) -> Result<(), WasiExtError> {
+    let mut arg = value;
+    ioctl(fd, FIFOSKERNW, Some(&mut arg))
+}
+
+pub fn fifo_set_kernel_read(fd: RawFd, value: c_int) -> Result<(), WasiExtError> {
+    let mut arg = value;
+    ioctl(fd, FIFOSKERNR, Some(&mut arg))
+}
+
+pub fn fifo_set_close_rm(fd: RawFd, value: c_int) -> Result<(), WasiExtError> {
+    let mut arg = value;
+    ioctl(fd, FIFOSCLOSERM, Some(&mut arg))
+}
+
+// WGET* commands are handled by the kernel wget device (major 2), they are not
+// terminal queries. WGETGS returns the HTTP status code of the request
+pub fn wget_get_status(fd: RawFd) -> Result<c_int, WasiExtError> {
+    let mut status: c_int = 0;
+    ioctl(fd, WGETGS, Some(&mut status))?;
+    Ok(status)
+}
+
+// Subsequent reads from the fd return the response headers
+pub fn wget_read_headers(fd: RawFd) -> Result<(), WasiExtError> {
+    ioctl::<c_void>(fd, WGETRH, None)
+}
+
+// Subsequent reads from the fd return the response body
+pub fn wget_read_body(fd: RawFd) -> Result<(), WasiExtError> {
+    ioctl::<c_void>(fd, WGETRB, None)
+}
+
+pub fn fcntl(fd: Fd, cmd: FcntlCommand) -> Result<i32, WasiExtError> {
+    let result = match cmd {
+        FcntlCommand::F_MVFD { min_fd_num } => unsafe {
+            let mut min_fd = min_fd_num;
+            wasi_ext_lib_generated::wasi_ext_fcntl(
+                fd as c_int,
+                wasi_ext_lib_generated::FcntlCommand_F_MVFD,
+                (&mut min_fd as *mut u32) as *mut c_void,
+            )
+        },
+        FcntlCommand::F_DUPFD { min_fd_num } => unsafe {
+            let mut min_fd = min_fd_num;
+            wasi_ext_lib_generated::wasi_ext_fcntl(
+                fd as c_int,
+                wasi_ext_lib_generated::FcntlCommand_F_DUPFD,
+                (&mut min_fd as *mut u32) as *mut c_void,
+            )
+        },
+        FcntlCommand::F_GETFD => unsafe {
+            let null_ptr = ptr::null_mut::<c_void>();
+            wasi_ext_lib_generated::wasi_ext_fcntl(
+                fd as c_int,
+                wasi_ext_lib_generated::FcntlCommand_F_GETFD,
+                null_ptr,
+            )
+        },
+        FcntlCommand::F_SETFD { flags } => unsafe {
+            let mut flags = flags;
+            wasi_ext_lib_generated::wasi_ext_fcntl(
+                fd as c_int,
+                wasi_ext_lib_generated::FcntlCommand_F_SETFD,
+                (&mut flags as *mut wasi::Fdflags) as *mut c_void,
+            )
+        },
+        FcntlCommand::F_GETFL => unsafe {
+            let null_ptr = ptr::null_mut::<c_void>();
+            wasi_ext_lib_generated::wasi_ext_fcntl(
+                fd as c_int,
+                wasi_ext_lib_generated::FcntlCommand_F_GETFL,
+                null_ptr,
+            )
+        },
+        FcntlCommand::F_SETFL { flags } => unsafe {
+            let mut flags = flags;
+            wasi_ext_lib_generated::wasi_ext_fcntl(
+                fd as c_int,
+                wasi_ext_lib_generated::FcntlCommand_F_SETFL,
+                (&mut flags as *mut wasi::Fdflags) as *mut c_void,
+            )
+        },
+        FcntlCommand::F_GETLK { lock } => {
+            return get_lock(fd, lock).map(|held| held.map_or(0, |held| held.pid.into_raw()));
+        }
+        FcntlCommand::F_SETLK { lock } => unsafe {
+            let mut raw = lock.to_raw();
+            wasi_ext_lib_generated::wasi_ext_fcntl(
+                fd as c_int,
+                wasi_ext_lib_generated::FcntlCommand_F_SETLK,
+                (&mut raw as *mut wasi_ext_lib_generated::Flock) as *mut c_void,
+            )
+        },
+        FcntlCommand::F_SETLKW { lock } => unsafe {
+            let mut raw = lock.to_raw();
+            wasi_ext_lib_generated::wasi_ext_fcntl(
+                fd as c_int,
+                wasi_ext_lib_generated::FcntlCommand_F_SETLKW,
+                (&mut raw as *mut wasi_ext_lib_generated::Flock) as *mut c_void,
+            )
+        },
+    };
+
+    if result < 0 {
+        Err(WasiExtError::from_raw(-result))
+    } else {
+        Ok(result)
+    }
+}
+
+// Returns the first lock that would block `lock` from being taken, None if it
+// can be taken right now. A contended F_SETLK fails with EAGAIN while kernels
+// without record locking fail with ENOSYS, so callers can tell them apart
+pub fn get_lock(fd: Fd, lock: RecordLock) -> Result<Option<RecordLock>, WasiExtError> {
+    let mut raw = lock.to_raw();
+    let result = unsafe {
+        wasi_ext_lib_generated::wasi_ext_fcntl(
+            fd as c_int,
+            wasi_ext_lib_generated::FcntlCommand_F_GETLK,
+            (&mut raw as *mut wasi_ext_lib_generated::Flock) as *mut c_void,
+        )
+    };
+
+    if result < 0 {
+        return Err(WasiExtError::from_raw(-result));
+    }
+    let held = RecordLock::from_raw(&raw)?;
+    if held.lock_type == LockType::Unlock {
+        Ok(None)
+    } else {
+        Ok(Some(held))
+    }
+}
+
+pub fn dup(fd: Fd) -> Result<Fd, WasiExtError> {
+    fcntl(fd, FcntlCommand::F_DUPFD { min_fd_num: 0 }).map(|new_fd| new_fd as Fd)
+}
+
+pub fn dup_cloexec(fd: Fd) -> Result<Fd, WasiExtError> {
+    let new_fd = dup(fd)?;
+    if let Err(e) = fcntl(
+        new_fd,
+        FcntlCommand::F_SETFD {
+            flags: WASI_EXT_FDFLAG_CLOEXEC,
+        },
+    ) {
+        let _ = unsafe { wasi::fd_close(new_fd) };
+        return Err(e);
+    }
+    Ok(new_fd)
+}
+
+pub fn dup2(src: Fd, dst: Fd) -> Result<Fd, WasiExtError> {
+    if src == dst {
+        // only check that the fd is valid, like POSIX dup2
+        fcntl(src, FcntlCommand::F_GETFD)?;
+        return Ok(dst);
+    }
+    // We assume fd_renumber behaves like dup2, same as F_MVFD does
+    unsafe { wasi::fd_renumber(src, dst) }?;
+    Ok(dst)
+}
+
+// Closing an fd that is not open fails with EBADF
+pub fn close(fd: Fd) -> Result<(), WasiExtError> {
+    unsafe { wasi::fd_close(fd) }?;
+    Ok(())
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct CloseRangeFlags: c_uint {
+        // mark the fds close-on-exec instead of closing them
+        const CLOEXEC = wasi_ext_lib_generated::CLOSE_RANGE_CLOEXEC;
+    }
+}
+
+// Closes every open fd from lo to hi inclusive in a single kernel call, fds in
+// the range that are not open are skipped. Pass Fd::MAX as hi to close
+// everything from lo on
+pub fn close_range(lo: Fd, hi: Fd, flags: CloseRangeFlags) -> Result<(), WasiExtError> {
+    if lo > hi {
+        return Err(WasiExtError::Errno(wasi::ERRNO_INVAL));
+    }
+    match unsafe { wasi_ext_lib_generated::wasi_ext_close_range(lo, hi, flags.bits()) } {
+        0 => Ok(()),
+        e => Err(WasiExtError::from_raw(e)),
+    }
+}
+
+// Probes every fd below _MAX_FD_NUM the same way F_DUPFD looks for a free one,
+// the result is sorted and can be stale as soon as another fd is opened
+pub fn list_open_fds() -> Result<Vec<Fd>, WasiExtError> {
+    let mut fds = Vec::new();
+    for fd in 0..wasi_ext_lib_generated::_MAX_FD_NUM as Fd {
+        match unsafe { wasi::fd_fdstat_get(fd) } {
+            Ok(_) => fds.push(fd),
+            Err(wasi::ERRNO_BADF) => {}
+            Err(e) => return Err(WasiExtError::from(e)),
+        }
+    }
+    Ok(fds)
+}
+
+// Returns (read_end, write_end)
+pub fn pipe() -> Result<(Fd, Fd), WasiExtError> {
+    pipe_with_flags(0)
+}
+
+// Flags are set on both ends, e.g. WASI_EXT_FDFLAG_CLOEXEC or
+// wasi::FDFLAGS_NONBLOCK
+pub fn pipe_with_flags(flags: wasi::Fdflags) -> Result<(Fd, Fd), WasiExtError> {
+    let mut fds: [c_int; 2] = [-1; 2];
+    let result = unsafe { wasi_ext_lib_generated::wasi_ext_pipe(fds.as_mut_ptr(), flags) };
+
+    if result < 0 {
+        Err(WasiExtError::from_raw(-result))
+    } else {
+        Ok((fds[0] as Fd, fds[1] as Fd))
+    }
+}
+
+#[cfg(feature = "sockets")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressFamily {
+    Inet,
+    Inet6,
+}
+
+#[cfg(feature = "sockets")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketType {
+    Stream,
+    Datagram,
+}
+
+// Socket fds work with poll, redirects and close like any other fd. Kernels
+// without sockets fail with ENOSYS
+#[cfg(feature = "sockets")]
+pub fn socket(
+    domain: AddressFamily,
+    socket_type: SocketType,
+    protocol: c_int,
+) -> Result<Fd, WasiExtError> {
+    let domain = match domain {
+        AddressFamily::Inet => wasi_ext_lib_generated::WASI_EXT_AF_INET,
+        AddressFamily::Inet6 => wasi_ext_lib_generated::WASI_EXT_AF_INET6,
+    };
+    let socket_type = match socket_type {
+        SocketType::Stream => wasi_ext_lib_generated::WASI_EXT_SOCK_STREAM,
+        SocketType::Datagram => wasi_ext_lib_generated::WASI_EXT_SOCK_DGRAM,
+    };
+    let result = unsafe {
+        wasi_ext_lib_generated::wasi_ext_socket(domain as c_int, socket_type as c_int, protocol)
+    };
+    if result < 0 {
+        Err(WasiExtError::from_raw(-result))
+    } else {
+        Ok(result as Fd)
+    }
+}
+
+#[cfg(feature = "sockets")]
+pub fn bind(fd: Fd, addr: &std::net::SocketAddr) -> Result<(), WasiExtError> {
+    let c_addr = CString::new(addr.to_string())?;
+    match unsafe { wasi_ext_lib_generated::wasi_ext_bind(fd as c_int, c_addr.as_ptr()) } {
+        0 => Ok(()),
+        e => Err(WasiExtError::from_raw(e)),
+    }
+}
+
+#[cfg(feature = "sockets")]
+pub fn connect(fd: Fd, addr: &std::net::SocketAddr) -> Result<(), WasiExtError> {
+    let c_addr = CString::new(addr.to_string())?;
+    match unsafe { wasi_ext_lib_generated::wasi_ext_connect(fd as c_int, c_addr.as_ptr()) } {
+        0 => Ok(()),
+        e => Err(WasiExtError::from_raw(e)),
+    }
+}
+
+#[cfg(feature = "sockets")]
+pub fn listen(fd: Fd, backlog: u32) -> Result<(), WasiExtError> {
+    let backlog = c_int::try_from(backlog).unwrap_or(c_int::MAX);
+    match unsafe { wasi_ext_lib_generated::wasi_ext_listen(fd as c_int, backlog) } {
+        0 => Ok(()),
+        e => Err(WasiExtError::from_raw(e)),
+    }
+}
+
+// Uses WASI sock_accept. On a listening socket with FDFLAGS_NONBLOCK it fails
+// with EAGAIN when no connection is pending, flags are set on the accepted fd
+#[cfg(feature = "sockets")]
+pub fn accept(fd: Fd, flags: wasi::Fdflags) -> Result<Fd, WasiExtError> {
+    Ok(unsafe { wasi::sock_accept(fd, flags) }?)
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PollFlags: u8 {
+        const READABLE = 1 << 0;
+        const WRITABLE = 1 << 1;
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct MemfdFlags: c_uint {
+        const CLOEXEC = wasi_ext_lib_generated::MFD_CLOEXEC;
+    }
+}
+
+// Creates an anonymous file kept in memory until its last fd is closed. The
+// name is only a label, it never appears in the filesystem
+pub fn memfd_create(name: &str, flags: MemfdFlags) -> Result<Fd, WasiExtError> {
+    let c_name = CString::new(name)?;
+
+    let result =
+        unsafe { wasi_ext_lib_generated::wasi_ext_memfd_create(c_name.as_ptr(), flags.bits()) };
+
+    if result < 0 {
+        Err(WasiExtError::from_raw(-result))
+    } else {
+        Ok(result as Fd)
+    }
+}
+
+// Copies up to len bytes without passing them through userspace, from and to
+// the current offsets which are advanced. Returns the number of bytes copied,
+// 0 at the end of src. Kernels without support fail with ENOSYS, callers can
+// fall back to a read and write loop then
+pub fn copy_file_range(src: Fd, dst: Fd, len: usize) -> Result<usize, WasiExtError> {
+    // the count is returned as an int, longer copies are cut short like reads
+    let len = len.min(c_int::MAX as usize);
+    let result = unsafe {
+        wasi_ext_lib_generated::wasi_ext_copy_file_range(src as c_int, dst as c_int, len)
+    };
+
+    if result < 0 {
+        Err(WasiExtError::from_raw(-result))
+    } else {
+        Ok(result as usize)
+    }
+}
+
+// Meant for fds with O_NONBLOCK set through F_SETFL, Ok(None) means the
+// operation would block
+pub fn read_nonblocking(fd: Fd, buf: &mut [u8]) -> Result<Option<usize>, WasiExtError> {
+    let iovec = wasi::Iovec {
+        buf: buf.as_mut_ptr(),
+        buf_len: buf.len(),
+    };
+    match unsafe { wasi::fd_read(fd, &[iovec]) } {
+        Ok(n) => Ok(Some(n)),
+        Err(wasi::ERRNO_AGAIN) => Ok(None),
+        Err(e) => Err(WasiExtError::from(e)),
+    }
+}
+
+pub fn write_nonblocking(fd: Fd, buf: &[u8]) -> Result<Option<usize>, WasiExtError> {
+    let ciovec = wasi::Ciovec {
+        buf: buf.as_ptr(),
+        buf_len: buf.len(),
+    };
+    match unsafe { wasi::fd_write(fd, &[ciovec]) } {
+        Ok(n) => Ok(Some(n)),
+        Err(wasi::ERRNO_AGAIN) => Ok(None),
+        Err(e) => Err(WasiExtError::from(e)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PollResult {
+    pub fd: Fd,
+    pub events: PollFlags,
+    pub hangup: bool,
+    // set if the subscription failed, e.g. with EBADF for a closed fd
+    pub error: Option<WasiExtError>,
+}
+
+fn clock_subscription(userdata: wasi::Userdata, timeout: Duration) -> wasi::Subscription {
+    wasi::Subscription {
+        userdata,
+        u: wasi::SubscriptionU {
+            tag: wasi::EVENTTYPE_CLOCK.raw(),
+            u: wasi::SubscriptionUU {
+                clock: wasi::SubscriptionClock {
+                    id: wasi::CLOCKID_MONOTONIC,
+                    timeout: u64::try_from(timeout.as_nanos()).unwrap_or(u64::MAX),
+                    precision: 0,
+                    flags: 0,
+                },
+            },
+        },
+    }
+}
+
+// Waits until any of the fds is ready or the timeout elapses, in which case
+// the result is empty. With no timeout the call blocks until an fd is ready
+pub fn poll(
+    fds: &[(Fd, PollFlags)],
+    timeout: Option<Duration>,
+) -> Result<Vec<PollResult>, WasiExtError> {
+    // userdata of fd subscriptions is the index in fds
+    const TIMEOUT_USERDATA: wasi::Userdata = u64::MAX;
+
+    let mut subscriptions = Vec::new();
+    for (i, (fd, flags)) in fds.iter().enumerate() {
+        if flags.contains(PollFlags::READABLE) {
+            subscriptions.push(wasi::Subscription {
+                userdata: i as wasi::Userdata,
+                u: wasi::SubscriptionU {
+                    tag: wasi::EVENTTYPE_FD_READ.raw(),
+                    u: wasi::SubscriptionUU {
+                        fd_read: wasi::SubscriptionFdReadwrite {
+                            file_descriptor: *fd,
+                        },
+                    },
+                },
+            });
+        }
+        if flags.contains(PollFlags::WRITABLE) {
+            subscriptions.push(wasi::Subscription {
+                userdata: i as wasi::Userdata,
+                u: wasi::SubscriptionU {
+                    tag: wasi::EVENTTYPE_FD_WRITE.raw(),
+                    u: wasi::SubscriptionUU {
+                        fd_write: wasi::SubscriptionFdReadwrite {
+                            file_descriptor: *fd,
+                        },
+                    },
+                },
+            });
+        }
+    }
+    if let Some(t) = timeout {
+        subscriptions.push(clock_subscription(TIMEOUT_USERDATA, t));
+    }
+    if subscriptions.is_empty() {
+        return Err(WasiExtError::Errno(wasi::ERRNO_INVAL));
+    }
+
+    let mut events: Vec<wasi::Event> = vec![unsafe { mem::zeroed() }; subscriptions.len()];
+    let n_events = unsafe {
+        wasi::poll_oneoff(
+            subscriptions.as_ptr(),
+            events.as_mut_ptr(),
+            subscriptions.len(),
+        )
+    }?;
+
+    let mut results: Vec<(usize, PollResult)> = Vec::new();
+    for event in &events[..n_events] {
+        if event.userdata == TIMEOUT_USERDATA {
+            continue;
+        }
+        let idx = event.userdata as usize;
+        let result = match results.iter_mut().find(|(i, _)| *i == idx) {
+            Some((_, r)) => r,
+            None => {
+                results.push((
+                    idx,
+                    PollResult {
+                        fd: fds[idx].0,
+                        events: PollFlags::empty(),
+                        hangup: false,
+                        error: None,
+                    },
+                ));
+                &mut results.last_mut().unwrap().1
+            }
+        };
+        if event.type_ == wasi::EVENTTYPE_FD_READ {
+            result.events |= PollFlags::READABLE;
+        } else if event.type_ == wasi::EVENTTYPE_FD_WRITE {
+            result.events |= PollFlags::WRITABLE;
+        }
+        if event.fd_readwrite.flags & wasi::EVENTRWFLAGS_FD_READWRITE_HANGUP != 0 {
+            result.hangup = true;
+        }
+        if event.error != wasi::ERRNO_SUCCESS {
+            result.error = Some(WasiExtError::from(event.error));
+        }
+    }
+    Ok(results.into_iter().map(|(_, r)| r).collect())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SleepError {
+    // cut short by a signal, carries the time left
+    Interrupted(Duration),
+    Failed(WasiExtError),
+}
+
+impl fmt::Display for SleepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SleepError::Interrupted(remaining) => {
+                write!(f, "interrupted with {remaining:?} left")
+            }
+            SleepError::Failed(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl error::Error for SleepError {}
+
+impl From<SleepError> for WasiExtError {
+    fn from(err: SleepError) -> Self {
+        match err {
+            SleepError::Interrupted(_) => WasiExtError::Errno(wasi::ERRNO_INTR),
+            SleepError::Failed(e) => e,
+        }
+    }
+}
+
+// Only EINTR counts as an interruption, an interrupted sleep with no time
+// left is reported as finished
+pub fn sleep(duration: Duration) -> Result<(), SleepError> {
+    let start = Instant::now();
+    let subscription = clock_subscription(0, duration);
+    let mut event: wasi::Event = unsafe { mem::zeroed() };
+    match unsafe { wasi::poll_oneoff(&subscription, &mut event, 1) } {
+        Ok(1) if event.error == wasi::ERRNO_SUCCESS => Ok(()),
+        Ok(1) => Err(SleepError::Failed(WasiExtError::from(event.error))),
+        Ok(_) => Err(SleepError::Failed(WasiExtError::Errno(wasi::ERRNO_IO))),
+        Err(wasi::ERRNO_INTR) => match duration.saturating_sub(start.elapsed()) {
+            remaining if remaining.is_zero() => Ok(()),
+            remaining => Err(SleepError::Interrupted(remaining)),
+        },
+        Err(e) => Err(SleepError::Failed(WasiExtError::from(e))),
+    }
+}
+
+// Paths are passed as raw bytes like in chdir, they do not have to be valid
+// UTF-8
+pub fn mount<S: AsRef<Path>, T: AsRef<Path>>(
+    source_path: S,
+    target_path: T,
+    filesystem_type: &str,
+    opts: u64,
+    data: &str,
+) -> Result<(), WasiExtError> {
+    let c_source_path = CString::new(source_path.as_ref().as_os_str().as_bytes())?;
+    let c_target_path = CString::new(target_path.as_ref().as_os_str().as_bytes())?;
+
+    let c_filesystem_type = CString::new(filesystem_type)?;
+    let c_data = CString::new(data)?;
+
+    mount_cstr(
+        &c_source_path,
+        &c_target_path,
+        &c_filesystem_type,
+        opts,
+        &c_data,
+    )
+}
+
+pub fn mount_cstr(
+    source_path: &CStr,
+    target_path: &CStr,
+    filesystem_type: &CStr,
+    opts: u64,
+    data: &CStr,
+) -> Result<(), WasiExtError> {
+    let result = unsafe {
+        wasi_ext_lib_generated::wasi_ext_mount(
+            -1,
+            source_path.as_ptr(),
+            -1,
+            target_path.as_ptr(),
+            filesystem_type.as_ptr(),
+            opts,
+            data.as_ptr(),
+        )
+    };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+pub fn mount_with_flags<S: AsRef<Path>, T: AsRef<Path>>(
+    source_path: S,
+    target_path: T,
+    filesystem_type: &str,
+    flags: MountFlags,
+    options: &MountOptions,
+) -> Result<(), WasiExtError> {
+    mount(
+        source_path,
+        target_path,
+        filesystem_type,
+        flags.bits(),
+        &options.to_data()?,
+    )
+}
+
+pub fn umount(path: &str) -> Result<(), WasiExtError> {
+    let c_path = CString::new(path)?;
+    umount_cstr(&c_path)
+}
+
+pub fn umount_cstr(path: &CStr) -> Result<(), WasiExtError> {
+    let result = unsafe { wasi_ext_lib_generated::wasi_ext_umount(path.as_ptr()) };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+// Unmounts the target on drop, errors are ignored then. Use unmount to see
+// them or leak to keep the mount
+pub struct MountGuard {
+    target_path: Option<CString>,
+}
+
+impl MountGuard {
+    pub fn unmount(mut self) -> Result<(), WasiExtError> {
+        match self.target_path.take() {
+            Some(target_path) => umount_cstr(&target_path),
+            None => Ok(()),
+        }
+    }
+
+    pub fn leak(mut self) {
+        self.target_path = None;
+    }
+}
+
+impl Drop for MountGuard {
+    fn drop(&mut self) {
+        if let Some(target_path) = self.target_path.take() {
+            let _ = umount_cstr(&target_path);
+        }
+    }
+}
+
+// Like mount_with_flags, the mount lasts as long as the returned guard
+pub fn mount_scoped<S: AsRef<Path>, T: AsRef<Path>>(
+    source_path: S,
+    target_path: T,
+    filesystem_type: &str,
+    flags: MountFlags,
+    options: &MountOptions,
+) -> Result<MountGuard, WasiExtError> {
+    let c_source_path = CString::new(source_path.as_ref().as_os_str().as_bytes())?;
+    let c_target_path = CString::new(target_path.as_ref().as_os_str().as_bytes())?;
+    let c_filesystem_type = CString::new(filesystem_type)?;
+    let c_data = CString::new(options.to_data()?)?;
+
+    mount_cstr(
+        &c_source_path,
+        &c_target_path,
+        &c_filesystem_type,
+        flags.bits(),
+        &c_data,
+    )?;
+    Ok(MountGuard {
+        target_path: Some(c_target_path),
+    })
+}
+
+pub fn mkdev(maj: i32, min: i32) -> i32 {
+    (maj << 20) | min
+}
+
+// Same encoding as MKDEV: 12 bits of major above 20 bits of minor. new
+// truncates larger values to their field width, try_new rejects them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Dev(u32);
+
+impl Dev {
+    const MINOR_BITS: u32 = 20;
+    const MINOR_MASK: u32 = (1 << Self::MINOR_BITS) - 1;
+    const MAJOR_MASK: u32 = (1 << (u32::BITS - Self::MINOR_BITS)) - 1;
+    pub const MAJOR_MAX: u32 = Self::MAJOR_MASK;
+    pub const MINOR_MAX: u32 = Self::MINOR_MASK;
+
+    pub fn new(major: u32, minor: u32) -> Self {
+        Dev(((major & Self::MAJOR_MASK) << Self::MINOR_BITS) | (minor & Self::MINOR_MASK))
+    }
+
+    pub fn try_new(major: u32, minor: u32) -> Result<Self, WasiExtError> {
+        if major > Self::MAJOR_MAX || minor > Self::MINOR_MAX {
+            return Err(WasiExtError::Errno(wasi::ERRNO_INVAL));
+        }
+        Ok(Dev::new(major, minor))
+    }
+
+    pub fn from_raw(raw: i32) -> Self {
+        Dev(raw as u32)
+    }
+
+    pub fn major(&self) -> u32 {
+        self.0 >> Self::MINOR_BITS
+    }
+
+    pub fn minor(&self) -> u32 {
+        self.0 & Self::MINOR_MASK
+    }
+
+    pub fn raw(&self) -> i32 {
+        self.0 as i32
+    }
+}
+
+pub fn mknod(path: &str, dev: Dev) -> Result<(), WasiExtError> {
+    let c_path = CString::new(path)?;
+    mknod_cstr(&c_path, dev)
+}
+
+pub fn mknod_cstr(path: &CStr, dev: Dev) -> Result<(), WasiExtError> {
+    let result = unsafe { wasi_ext_lib_generated::wasi_ext_mknod(path.as_ptr(), dev.raw()) };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+// Sets the new mask and returns the previous one
+pub fn umask(mask: u32) -> Result<u32, WasiExtError> {
+    let result = unsafe { wasi_ext_lib_generated::wasi_ext_umask(mask as c_int) };
+
+    if result < 0 {
+        Err(WasiExtError::from_raw(-result))
+    } else {
+        Ok(result as u32)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    // CPU time in seconds
+    Cpu,
+    // One more than the highest fd number that can be opened
+    Nofile,
+    // Address space size in bytes
+    As,
+}
+
+impl Resource {
+    fn raw(self) -> c_int {
+        let resource = match self {
+            Resource::Cpu => wasi_ext_lib_generated::RLIMIT_CPU,
+            Resource::Nofile => wasi_ext_lib_generated::RLIMIT_NOFILE,
+            Resource::As => wasi_ext_lib_generated::RLIMIT_AS,
+        };
+        resource as c_int
+    }
+}
+
+pub const RLIM_INFINITY: u64 = u64::MAX;
+
+// The soft limit is the one enforced, it can be raised up to the hard limit.
+// Either can be RLIM_INFINITY
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RLimit {
+    pub soft: u64,
+    pub hard: u64,
+}
+
+// Limits are inherited by spawned children. Kernels that do not enforce a
+// resource fail with ENOSYS
+pub fn getrlimit(resource: Resource) -> Result<RLimit, WasiExtError> {
+    let mut limit: wasi_ext_lib_generated::Rlimit = unsafe { mem::zeroed() };
+
+    let result = unsafe { wasi_ext_lib_generated::wasi_ext_getrlimit(resource.raw(), &mut limit) };
+
+    if result == 0 {
+        Ok(RLimit {
+            soft: limit.cur,
+            hard: limit.max,
+        })
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+// Raising the hard limit fails with EPERM, a soft limit above the hard one
+// with EINVAL
+pub fn setrlimit(resource: Resource, limit: RLimit) -> Result<(), WasiExtError> {
+    let limit = wasi_ext_lib_generated::Rlimit {
+        cur: limit.soft,
+        max: limit.hard,
+    };
+
+    let result = unsafe { wasi_ext_lib_generated::wasi_ext_setrlimit(resource.raw(), &limit) };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+pub fn symlink(target: &str, linkpath: &str) -> Result<(), WasiExtError> {
+    let c_target = CString::new(target)?;
+    let c_linkpath = CString::new(linkpath)?;
+
+    let result =
+        unsafe { wasi_ext_lib_generated::wasi_ext_symlink(c_target.as_ptr(), c_linkpath.as_ptr()) };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+// Both names refer to the same inode afterwards. Fails with EEXIST if new
+// already exists and with EXDEV if the paths are on different mounts
+pub fn link(existing: &str, new: &str) -> Result<(), WasiExtError> {
+    let c_existing = CString::new(existing)?;
+    let c_new = CString::new(new)?;
+
+    let result =
+        unsafe { wasi_ext_lib_generated::wasi_ext_link(c_existing.as_ptr(), c_new.as_ptr()) };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+// Fails with EINVAL if the path is not a symlink
+pub fn readlink(path: &str) -> Result<String, WasiExtError> {
+    let c_path = CString::new(path)?;
+
+    let buf = read_growing_buf(|buf, buf_len| unsafe {
+        wasi_ext_lib_generated::wasi_ext_readlink(c_path.as_ptr(), buf, buf_len)
+    })?;
+    String::from_utf8(buf).map_err(|_| WasiExtError::Errno(wasi::ERRNO_ILSEQ))
+}
+
+// Resolved by the kernel, relative paths are taken from the kernel's cwd.
+// Fails with ENOENT if any component does not exist
+pub fn realpath(path: &str) -> Result<String, WasiExtError> {
+    let c_path = CString::new(path)?;
+
+    let buf = read_growing_buf(|buf, buf_len| unsafe {
+        wasi_ext_lib_generated::wasi_ext_realpath(c_path.as_ptr(), buf, buf_len)
+    })?;
+    String::from_utf8(buf).map_err(|_| WasiExtError::Errno(wasi::ERRNO_ILSEQ))
+}
+
+// Ok(false) means the access was denied or the path does not exist
+pub fn access(path: &str, mode: AccessMode) -> Result<bool, WasiExtError> {
+    let c_path = CString::new(path)?;
+
+    let result = unsafe { wasi_ext_lib_generated::wasi_ext_access(c_path.as_ptr(), mode.bits()) };
+
+    match result {
+        0 => Ok(true),
+        e => match WasiExtError::from_raw(e) {
+            WasiExtError::Errno(
+                wasi::ERRNO_ACCES | wasi::ERRNO_PERM | wasi::ERRNO_NOENT | wasi::ERRNO_ROFS,
+            ) => Ok(false),
+            err => Err(err),
+        },
+    }
+}
+
+// The kernel applies the current umask to the mode
+pub fn mkdir(path: &str, mode: u32) -> Result<(), WasiExtError> {
+    let c_path = CString::new(path)?;
+
+    let result = unsafe { wasi_ext_lib_generated::wasi_ext_mkdir(c_path.as_ptr(), mode as c_int) };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+pub fn rmdir(path: &str) -> Result<(), WasiExtError> {
+    let c_path = CString::new(path)?;
+
+    let result = unsafe { wasi_ext_lib_generated::wasi_ext_rmdir(c_path.as_ptr()) };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+// Fails with EXDEV when the paths are on different mounts, callers should
+// fall back to copy and unlink. As in POSIX, a directory can only replace an
+// empty directory (ENOTEMPTY otherwise) and a file cannot replace a directory
+// (EISDIR)
+pub fn rename(from: &str, to: &str) -> Result<(), WasiExtError> {
+    let c_from = CString::new(from)?;
+    let c_to = CString::new(to)?;
+
+    let result = unsafe { wasi_ext_lib_generated::wasi_ext_rename(c_from.as_ptr(), c_to.as_ptr()) };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+// Filesystems without permission support fail with ENOTSUP
+pub fn chmod(path: &str, mode: u32) -> Result<(), WasiExtError> {
+    let c_path = CString::new(path)?;
+
+    let result = unsafe { wasi_ext_lib_generated::wasi_ext_chmod(c_path.as_ptr(), mode as c_int) };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+pub fn fchmod(fd: Fd, mode: u32) -> Result<(), WasiExtError> {
+    let result = unsafe { wasi_ext_lib_generated::wasi_ext_fchmod(fd as c_int, mode as c_int) };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+// -1 tells the kernel to leave the id unchanged, like in POSIX chown
+fn owner_id(id: Option<u32>) -> c_int {
+    id.map_or(-1, |id| id as c_int)
+}
+
+// None leaves the id unchanged, filesystems without ownership fail with
+// ENOTSUP
+pub fn chown(path: &str, uid: Option<u32>, gid: Option<u32>) -> Result<(), WasiExtError> {
+    let c_path = CString::new(path)?;
+
+    let result = unsafe {
+        wasi_ext_lib_generated::wasi_ext_chown(c_path.as_ptr(), owner_id(uid), owner_id(gid))
+    };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+pub fn fchown(fd: Fd, uid: Option<u32>, gid: Option<u32>) -> Result<(), WasiExtError> {
+    let result = unsafe {
+        wasi_ext_lib_generated::wasi_ext_fchown(fd as c_int, owner_id(uid), owner_id(gid))
+    };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+// Growing a file fills the new space with zeros, shrinking discards the tail
+pub fn truncate(path: &str, len: u64) -> Result<(), WasiExtError> {
+    let c_path = CString::new(path)?;
+
+    let result = unsafe { wasi_ext_lib_generated::wasi_ext_truncate(c_path.as_ptr(), len) };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+pub fn ftruncate(fd: Fd, len: u64) -> Result<(), WasiExtError> {
+    unsafe { wasi::fd_filestat_set_size(fd, len) }?;
+    Ok(())
+}
+
+// Filesystems without a backing store treat sync as a no-op and succeed
+pub fn fsync(fd: Fd) -> Result<(), WasiExtError> {
+    unsafe { wasi::fd_sync(fd) }?;
+    Ok(())
+}
+
+pub fn fdatasync(fd: Fd) -> Result<(), WasiExtError> {
+    unsafe { wasi::fd_datasync(fd) }?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Whence {
+    Set,
+    Cur,
+    End,
+}
+
+impl From<Whence> for wasi::Whence {
+    fn from(whence: Whence) -> Self {
+        match whence {
+            Whence::Set => wasi::WHENCE_SET,
+            Whence::Cur => wasi::WHENCE_CUR,
+            Whence::End => wasi::WHENCE_END,
+        }
+    }
+}
+
+// Returns the new absolute offset, pipes and ttys fail with ESPIPE
+pub fn lseek(fd: Fd, offset: i64, whence: Whence) -> Result<u64, WasiExtError> {
+    Ok(unsafe { wasi::fd_seek(fd, offset, whence.into()) }?)
+}
+
+// random_get always fills the whole buffer, so there are no short reads to
+// retry
+pub fn getrandom(buf: &mut [u8]) -> Result<(), WasiExtError> {
+    unsafe { wasi::random_get(buf.as_mut_ptr(), buf.len()) }?;
+    Ok(())
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct FlockOp: c_int {
+        const SHARED = wasi_ext_lib_generated::LOCK_SH as c_int;
+        const EXCLUSIVE = wasi_ext_lib_generated::LOCK_EX as c_int;
+        const NONBLOCK = wasi_ext_lib_generated::LOCK_NB as c_int;
+        const UNLOCK = wasi_ext_lib_generated::LOCK_UN as c_int;
+    }
+}
+
+// Locks are advisory, with NONBLOCK a contended lock fails with EWOULDBLOCK
+// instead of waiting
+pub fn flock(fd: Fd, op: FlockOp) -> Result<(), WasiExtError> {
+    let result = unsafe { wasi_ext_lib_generated::wasi_ext_flock(fd as c_int, op.bits()) };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+// dev and rdev use the same encoding as mkdev
+#[derive(Debug, Clone, Copy)]
+pub struct Metadata(wasi_ext_lib_generated::Stat);
+
+impl Metadata {
+    // Both device numbers use the Dev encoding, rdev can be passed to mknod
+    pub fn dev(&self) -> Dev {
+        Dev::from_raw(self.0.dev as i32)
+    }
+
+    pub fn ino(&self) -> u64 {
+        self.0.ino
+    }
+
+    // Device number of the node itself, only meaningful for device files
+    pub fn rdev(&self) -> Dev {
+        Dev::from_raw(self.0.rdev as i32)
+    }
+
+    pub fn mode(&self) -> u32 {
+        self.0.mode
+    }
+
+    pub fn nlink(&self) -> u64 {
+        self.0.nlink
+    }
+
+    pub fn size(&self) -> u64 {
+        self.0.size
+    }
+
+    pub fn accessed(&self) -> Duration {
+        Duration::from_nanos(self.0.atim)
+    }
+
+    pub fn modified(&self) -> Duration {
+        Duration::from_nanos(self.0.mtim)
+    }
+
+    pub fn changed(&self) -> Duration {
+        Duration::from_nanos(self.0.ctim)
+    }
+
+    pub fn file_type(&self) -> wasi::Filetype {
+        match self.0.filetype {
+            n if n <= wasi::FILETYPE_SYMBOLIC_LINK.raw() => unsafe {
+                mem::transmute::<u8, wasi::Filetype>(n)
+            },
+            _ => wasi::FILETYPE_UNKNOWN,
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type() == wasi::FILETYPE_DIRECTORY
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.file_type() == wasi::FILETYPE_REGULAR_FILE
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.file_type() == wasi::FILETYPE_SYMBOLIC_LINK
+    }
+
+    pub fn is_char_device(&self) -> bool {
+        self.file_type() == wasi::FILETYPE_CHARACTER_DEVICE
+    }
+
+    pub fn is_block_device(&self) -> bool {
+        self.file_type() == wasi::FILETYPE_BLOCK_DEVICE
+    }
+}
+
+fn stat_path(path: &str, follow_symlinks: bool) -> Result<Metadata, WasiExtError> {
+    let c_path = CString::new(path)?;
+    let mut stat: wasi_ext_lib_generated::Stat = unsafe { mem::zeroed() };
+
+    let result = unsafe {
+        wasi_ext_lib_generated::wasi_ext_stat(c_path.as_ptr(), follow_symlinks as c_int, &mut stat)
+    };
+
+    if result == 0 {
+        Ok(Metadata(stat))
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+pub fn stat(path: &str) -> Result<Metadata, WasiExtError> {
+    stat_path(path, true)
+}
+
+// Like stat but does not follow a trailing symlink
+pub fn lstat(path: &str) -> Result<Metadata, WasiExtError> {
+    stat_path(path, false)
+}
+
+pub fn fstat(fd: Fd) -> Result<Metadata, WasiExtError> {
+    let mut stat: wasi_ext_lib_generated::Stat = unsafe { mem::zeroed() };
+
+    let result = unsafe { wasi_ext_lib_generated::wasi_ext_fstat(fd as c_int, &mut stat) };
+
+    if result == 0 {
+        Ok(Metadata(stat))
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+// Virtual filesystems without block accounting report best-effort values, free
+// and available counts of 0 do not necessarily mean the filesystem is full
+#[derive(Debug, Clone, Copy)]
+pub struct StatVfs(wasi_ext_lib_generated::StatVfs);
+
+impl StatVfs {
+    pub fn block_size(&self) -> u64 {
+        self.0.bsize
+    }
+
+    pub fn blocks(&self) -> u64 {
+        self.0.blocks
+    }
+
+    pub fn free_blocks(&self) -> u64 {
+        self.0.bfree
+    }
+
+    // Free blocks usable by unprivileged processes
+    pub fn available_blocks(&self) -> u64 {
+        self.0.bavail
+    }
+
+    pub fn files(&self) -> u64 {
+        self.0.files
+    }
+
+    pub fn free_files(&self) -> u64 {
+        self.0.ffree
+    }
+
+    pub fn name_max(&self) -> u64 {
+        self.0.namemax
+    }
+}
+
+// Reports the filesystem the path resides on
+pub fn statvfs(path: &str) -> Result<StatVfs, WasiExtError> {
+    let c_path = CString::new(path)?;
+    let mut stat: wasi_ext_lib_generated::StatVfs = unsafe { mem::zeroed() };
+
+    let result = unsafe { wasi_ext_lib_generated::wasi_ext_statvfs(c_path.as_ptr(), &mut stat) };
+
+    if result == 0 {
+        Ok(StatVfs(stat))
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    name: OsString,
+    ino: u64,
+    file_type: wasi::Filetype,
+}
+
+impl DirEntry {
+    pub fn name(&self) -> &OsStr {
+        &self.name
+    }
+
+    pub fn ino(&self) -> u64 {
+        self.ino
+    }
+
+    // FILETYPE_UNKNOWN if the filesystem does not report types in listings,
+    // lstat the entry then
+    pub fn file_type(&self) -> wasi::Filetype {
+        self.file_type
+    }
+}
+
+// Lists the directory with fd_readdir, paging through it by cookie. Entries
+// come in the order returned by the filesystem, "." and ".." are kept if the
+// filesystem reports them
+pub fn readdir<P: AsRef<Path>>(path: P) -> Result<Vec<DirEntry>, WasiExtError> {
+    readdir_with_buf_len(path.as_ref(), DEFAULT_INITIAL_BUF_SIZE * 16)
+}
+
+// The initial buffer length is passed in for tests, which start with one too
+// small for a single entry
+fn readdir_with_buf_len(path: &Path, buf_len: usize) -> Result<Vec<DirEntry>, WasiExtError> {
+    let dir = fs::File::open(path).map_err(io_error_to_wasi)?;
+    let header_len = mem::size_of::<wasi::Dirent>();
+    let mut buf = vec![0u8; buf_len];
+    let mut cookie: wasi::Dircookie = wasi::DIRCOOKIE_START;
+    let mut entries = Vec::new();
+
+    loop {
+        let used = unsafe {
+            wasi::fd_readdir(dir.as_raw_fd() as Fd, buf.as_mut_ptr(), buf.len(), cookie)
+        }?;
+
+        // a full buffer may end with a truncated entry, it is read again
+        // starting from the cookie of the last complete one
+        let mut offset = 0;
+        let mut complete = 0;
+        while offset + header_len <= used {
+            let dirent =
+                unsafe { ptr::read_unaligned(buf[offset..].as_ptr() as *const wasi::Dirent) };
+            let name_start = offset + header_len;
+            let name_end = name_start + dirent.d_namlen as usize;
+            if name_end > used {
+                break;
+            }
+            entries.push(DirEntry {
+                name: OsString::from_vec(buf[name_start..name_end].to_vec()),
+                ino: dirent.d_ino,
+                file_type: dirent.d_type,
+            });
+            cookie = dirent.d_next;
+            offset = name_end;
+            complete += 1;
+        }
+
+        if used < buf.len() {
+            return Ok(entries);
+        }
+        if complete == 0 {
+            // a single entry does not fit
+            let len = buf.len() * 2;
+            buf.resize(len, 0);
+        }
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct OFlags: u32 {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        const CREATE = 1 << 2;
+        const TRUNCATE = 1 << 3;
+        const APPEND = 1 << 4;
+        const EXCLUSIVE = 1 << 5;
+        const CLOEXEC = 1 << 6;
+        // ELOOP if the last path component is a symlink
+        const NOFOLLOW = 1 << 7;
+        // ENOTDIR if the path is not a directory
+        const DIRECTORY = 1 << 8;
+    }
+}
+
+// The mode is only applied when the file gets created and is masked with the
+// umask like in mkdir, filesystems without permission support keep their
+// default mode. The fd is owned by the caller and can be used directly in
+// redirects, dup and close
+pub fn open(path: &str, flags: OFlags, mode: u32) -> Result<Fd, WasiExtError> {
+    if flags.intersects(OFlags::NOFOLLOW | OFlags::DIRECTORY) {
+        return open_from_parent(path, flags, mode);
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options
+        .read(flags.contains(OFlags::READ))
+        .write(flags.contains(OFlags::WRITE))
+        .append(flags.contains(OFlags::APPEND))
+        .truncate(flags.contains(OFlags::TRUNCATE));
+
+    // WASI opens files without a mode, try exclusive creation first to find out
+    // whether it has to be set
+    let (file, created) = if !flags.contains(OFlags::CREATE) {
+        (options.open(path), false)
+    } else if flags.contains(OFlags::EXCLUSIVE) {
+        (options.create_new(true).open(path), true)
+    } else {
+        match options.clone().create_new(true).open(path) {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => (options.open(path), false),
+            file => (file, true),
+        }
+    };
+    let fd = file.map_err(io_error_to_wasi)?.into_raw_fd() as Fd;
+
+    let result = init_opened_fd(fd, flags, mode, created);
+    if let Err(e) = result {
+        let _ = close(fd);
+        return Err(e);
+    }
+    Ok(fd)
+}
+
+// std cannot pass lookup flags or oflags, the last component is opened with
+// openat relative to its parent instead. Paths without one, like "/" or
+// ending in "..", cannot be symlinks and are always directories
+fn open_from_parent(path: &str, flags: OFlags, mode: u32) -> Result<Fd, WasiExtError> {
+    let name = match Path::new(path).file_name().and_then(OsStr::to_str) {
+        Some(name) => name,
+        None => {
+            let fd = open_dir(path)?;
+            if let Err(e) = init_opened_fd(fd, flags, mode, false) {
+                let _ = close(fd);
+                return Err(e);
+            }
+            return Ok(fd);
+        }
+    };
+    let parent = match Path::new(path).parent().and_then(Path::to_str) {
+        Some("") | None => ".",
+        Some(parent) => parent,
+    };
+
+    let dirfd = open_dir(parent)?;
+    let result = openat(dirfd, name, flags, mode);
+    let _ = close(dirfd);
+    result
+}
+
+// The umask can only be read by replacing it, the old one is put back right
+// away. Kernels without a umask do not mask modes
+fn current_umask() -> Result<u32, WasiExtError> {
+    match umask(0) {
+        Ok(mask) => umask(mask).map(|_| mask),
+        Err(WasiExtError::Errno(wasi::ERRNO_NOSYS)) => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+fn init_opened_fd(fd: Fd, flags: OFlags, mode: u32, created: bool) -> Result<(), WasiExtError> {
+    if created {
+        match fchmod(fd, mode & !current_umask()?) {
+            Err(WasiExtError::Errno(wasi::ERRNO_NOTSUP | wasi::ERRNO_NOSYS)) | Ok(()) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    if flags.contains(OFlags::CLOEXEC) {
+        fcntl(
+            fd,
+            FcntlCommand::F_SETFD {
+                flags: WASI_EXT_FDFLAG_CLOEXEC,
+            },
+        )?;
+    }
+    Ok(())
+}
+
+// Directory fd usable as the base of the *at functions, fails with ENOTDIR for
+// anything but a directory. The fd is owned by the caller
+pub fn open_dir(path: &str) -> Result<Fd, WasiExtError> {
+    let dir = fs::File::open(path).map_err(io_error_to_wasi)?;
+    let stat = unsafe { wasi::fd_filestat_get(dir.as_raw_fd() as Fd) }?;
+    if stat.filetype != wasi::FILETYPE_DIRECTORY {
+        return Err(WasiExtError::Errno(wasi::ERRNO_NOTDIR));
+    }
+    Ok(dir.into_raw_fd() as Fd)
+}
+
+// Same rights std requests, path rights are kept so that the fd can be used
+// with the *at functions if it is a directory
+fn open_rights(flags: OFlags) -> wasi::Rights {
+    let mut rights = wasi::RIGHTS_FD_ADVISE
+        | wasi::RIGHTS_FD_FDSTAT_SET_FLAGS
+        | wasi::RIGHTS_FD_FILESTAT_GET
+        | wasi::RIGHTS_FD_FILESTAT_SET_TIMES
+        | wasi::RIGHTS_FD_SEEK
+        | wasi::RIGHTS_FD_SYNC
+        | wasi::RIGHTS_FD_TELL
+        | wasi::RIGHTS_PATH_CREATE_DIRECTORY
+        | wasi::RIGHTS_PATH_CREATE_FILE
+        | wasi::RIGHTS_PATH_FILESTAT_GET
+        | wasi::RIGHTS_PATH_LINK_SOURCE
+        | wasi::RIGHTS_PATH_LINK_TARGET
+        | wasi::RIGHTS_PATH_OPEN
+        | wasi::RIGHTS_PATH_READLINK
+        | wasi::RIGHTS_PATH_REMOVE_DIRECTORY
+        | wasi::RIGHTS_PATH_RENAME_SOURCE
+        | wasi::RIGHTS_PATH_RENAME_TARGET
+        | wasi::RIGHTS_PATH_SYMLINK
+        | wasi::RIGHTS_PATH_UNLINK_FILE
+        | wasi::RIGHTS_POLL_FD_READWRITE;
+    if flags.contains(OFlags::READ) {
+        rights |= wasi::RIGHTS_FD_READ | wasi::RIGHTS_FD_READDIR;
+    }
+    if flags.intersects(OFlags::WRITE | OFlags::APPEND) {
+        rights |= wasi::RIGHTS_FD_WRITE
+            | wasi::RIGHTS_FD_DATASYNC
+            | wasi::RIGHTS_FD_ALLOCATE
+            | wasi::RIGHTS_FD_FILESTAT_SET_SIZE;
+    }
+    rights
+}
+
+// Like open but the path is resolved relative to dirfd, absolute paths and
+// paths escaping dirfd fail with ENOTCAPABLE
+pub fn openat(dirfd: Fd, path: &str, flags: OFlags, mode: u32) -> Result<Fd, WasiExtError> {
+    let rights = open_rights(flags);
+    let fdflags = if flags.contains(OFlags::APPEND) {
+        wasi::FDFLAGS_APPEND
+    } else {
+        0
+    };
+    let mut oflags = 0;
+    if flags.contains(OFlags::TRUNCATE) {
+        oflags |= wasi::OFLAGS_TRUNC;
+    }
+    if flags.contains(OFlags::DIRECTORY) {
+        oflags |= wasi::OFLAGS_DIRECTORY;
+    }
+    let lookup_flags = if flags.contains(OFlags::NOFOLLOW) {
+        0
+    } else {
+        wasi::LOOKUPFLAGS_SYMLINK_FOLLOW
+    };
+    let path_open = |oflags| unsafe {
+        wasi::path_open(dirfd, lookup_flags, path, oflags, rights, rights, fdflags)
+    };
+
+    // same as open, exclusive creation tells whether the mode has to be set
+    let (fd, created) = if !flags.contains(OFlags::CREATE) {
+        (path_open(oflags)?, false)
+    } else {
+        let exclusive = oflags | wasi::OFLAGS_CREAT | wasi::OFLAGS_EXCL;
+        match path_open(exclusive) {
+            Err(wasi::ERRNO_EXIST) if !flags.contains(OFlags::EXCLUSIVE) => {
+                (path_open(oflags)?, false)
+            }
+            fd => (fd?, true),
+        }
+    };
+
+    let result = init_opened_fd(fd, flags, mode, created);
+    if let Err(e) = result {
+        let _ = close(fd);
+        return Err(e);
+    }
+    Ok(fd)
+}
+
+// Removes a file relative to dirfd, directories have to be removed with
+// rmdirat
+pub fn unlinkat(dirfd: Fd, path: &str) -> Result<(), WasiExtError> {
+    unsafe { wasi::path_unlink_file(dirfd, path) }?;
+    Ok(())
+}
+
+pub fn rmdirat(dirfd: Fd, path: &str) -> Result<(), WasiExtError> {
+    unsafe { wasi::path_remove_directory(dirfd, path) }?;
+    Ok(())
+}
+
+// WASI creates directories without a mode, it is set afterwards through an fd
+// of the new directory and masked with the umask. Filesystems without
+// permission support keep their default mode
+pub fn mkdirat(dirfd: Fd, path: &str, mode: u32) -> Result<(), WasiExtError> {
+    let mode = mode & !current_umask()?;
+    unsafe { wasi::path_create_directory(dirfd, path) }?;
 
-    if result < 0 {
-        Err(-result)
-    } else {
-        Ok(result)
+    let rights = open_rights(OFlags::READ);
+    let fd = unsafe { wasi::path_open(dirfd, 0, path, wasi::OFLAGS_DIRECTORY, rights, rights, 0) }?;
+    let result = fchmod(fd, mode);
+    let _ = close(fd);
+    match result {
+        Err(WasiExtError::Errno(wasi::ERRNO_NOTSUP | wasi::ERRNO_NOSYS)) | Ok(()) => Ok(()),
+        Err(e) => Err(e),
     }
 }
 
-pub fn mount(
-    source_path: &str,
-    target_path: &str,
-    filesystem_type: &str,
-    opts: u64,
-    data: &str,
-) -> Result<(), ExitCode> {
-    let c_source_path = CString::new(source_path).unwrap();
-    let c_target_path = CString::new(target_path).unwrap();
+// Applies redirections to the current process, e.g. for builtins that run
+// without spawning. PipeIn and PipeOut make stdin or stdout a copy of their fd.
+// The replaced fds are restored on drop, closed ones are closed again. Buffered
+// std streams are not flushed, do that before the guard goes out of scope
+pub struct RedirectGuard {
+    // (target fd, copy of its original or None if it was closed), in the
+    // order they were first touched
+    saved: Vec<(Fd, Option<Fd>)>,
+    // copies are kept above every redirected fd so no redirect can clobber
+    // them
+    min_saved_fd: Fd,
+}
 
-    let c_filesystem_type = CString::new(filesystem_type).unwrap();
-    let c_data = CString::new(data).unwrap();
+impl RedirectGuard {
+    // Redirections are applied in order, on failure the ones already applied
+    // are rolled back
+    pub fn new(redirects: &[Redirect]) -> Result<Self, WasiExtError> {
+        let min_saved_fd = redirects
+            .iter()
+            .map(|redirect| match redirect {
+                Redirect::Duplicate { fd_src, fd_dst } => *fd_src.max(fd_dst),
+                redirect => redirect.target_fd(),
+            })
+            .max()
+            .map_or(10, |fd| fd.saturating_add(1).max(10));
+        let mut guard = RedirectGuard {
+            saved: Vec::new(),
+            min_saved_fd,
+        };
+        for redirect in redirects {
+            guard.apply(redirect)?;
+        }
+        Ok(guard)
+    }
 
-    let result = unsafe {
-        wasi_ext_lib_generated::wasi_ext_mount(
-            -1,
-            c_source_path.as_ptr(),
-            -1,
-            c_target_path.as_ptr(),
-            c_filesystem_type.as_ptr(),
-            opts,
-            c_data.as_ptr(),
-        )
-    };
+    fn save(&mut self, fd: Fd) -> Result<(), WasiExtError> {
+        if self.saved.iter().any(|(target, _)| *target == fd) {
+            return Ok(());
+        }
+        let copy = match fcntl(
+            fd,
+            FcntlCommand::F_DUPFD {
+                min_fd_num: self.min_saved_fd,
+            },
+        ) {
+            Ok(copy) => Some(copy as Fd),
+            Err(WasiExtError::Errno(wasi::ERRNO_BADF)) => None,
+            Err(e) => return Err(e),
+        };
+        self.saved.push((fd, copy));
+        if let Some(copy) = copy {
+            fcntl(
+                copy,
+                FcntlCommand::F_SETFD {
+                    flags: WASI_EXT_FDFLAG_CLOEXEC,
+                },
+            )?;
+        }
+        Ok(())
+    }
 
-    if result == 0 {
+    fn apply(&mut self, redirect: &Redirect) -> Result<(), WasiExtError> {
+        let fd = redirect.target_fd();
+        self.save(fd)?;
+
+        let (path, flags) = match redirect {
+            Redirect::Read(_, path) => (path, OFlags::READ),
+            Redirect::Write(_, path) => (path, OFlags::WRITE | OFlags::CREATE | OFlags::TRUNCATE),
+            Redirect::Append(_, path) => (path, OFlags::WRITE | OFlags::CREATE | OFlags::APPEND),
+            Redirect::ReadWrite(_, path) => (path, OFlags::READ | OFlags::WRITE | OFlags::CREATE),
+            Redirect::ReadWriteAppend(_, path) => (
+                path,
+                OFlags::READ | OFlags::WRITE | OFlags::CREATE | OFlags::APPEND,
+            ),
+            Redirect::PipeIn(fd_src)
+            | Redirect::PipeOut(fd_src)
+            | Redirect::Duplicate { fd_src, .. } => return dup2(*fd_src, fd).map(|_| ()),
+            Redirect::Close(_) => {
+                return match close(fd) {
+                    Err(WasiExtError::Errno(wasi::ERRNO_BADF)) | Ok(()) => Ok(()),
+                    Err(e) => Err(e),
+                }
+            }
+        };
+
+        let new_fd = open(path, flags, 0o644)?;
+        // the target may have been closed, then open already reused it
+        if new_fd != fd {
+            let result = dup2(new_fd, fd);
+            let _ = close(new_fd);
+            result?;
+        }
         Ok(())
-    } else {
-        Err(result)
     }
 }
 
-pub fn umount(path: &str) -> Result<(), ExitCode> {
-    let c_path = CString::new(path).unwrap();
+impl Drop for RedirectGuard {
+    fn drop(&mut self) {
+        for (fd, copy) in self.saved.drain(..).rev() {
+            match copy {
+                Some(copy) => {
+                    let _ = dup2(copy, fd);
+                    let _ = close(copy);
+                }
+                None => {
+                    let _ = close(fd);
+                }
+            }
+        }
+    }
+}
+
+// Runs f with the redirections applied and restores the fds afterwards
+pub fn with_redirects<F, R>(redirects: &[Redirect], f: F) -> Result<R, WasiExtError>
+where
+    F: FnOnce() -> R,
+{
+    let _guard = RedirectGuard::new(redirects)?;
+    Ok(f())
+}
 
-    let result = unsafe { wasi_ext_lib_generated::wasi_ext_umount(c_path.as_ptr()) };
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timestamp {
+    Now,
+    At(SystemTime),
+}
 
-    if result == 0 {
-        Ok(())
-    } else {
-        Err(result)
+impl From<SystemTime> for Timestamp {
+    fn from(time: SystemTime) -> Self {
+        Timestamp::At(time)
     }
 }
 
-pub fn mkdev(maj: i32, min: i32) -> i32 {
-    (maj << 20) | min
+// Returns the timestamp in nanoseconds and the fst_flags selecting it, times
+// before the epoch cannot be represented in WASI
+fn timestamp_to_wasi(
+    time: Option<Timestamp>,
+    set: wasi::Fstflags,
+    now: wasi::Fstflags,
+) -> Result<(wasi::Timestamp, wasi::Fstflags), WasiExtError> {
+    match time {
+        None => Ok((0, 0)),
+        Some(Timestamp::Now) => Ok((0, now)),
+        Some(Timestamp::At(time)) => {
+            let nanos = time
+                .duration_since(UNIX_EPOCH)
+                .map_err(|_| WasiExtError::Errno(wasi::ERRNO_INVAL))?
+                .as_nanos();
+            let nanos = wasi::Timestamp::try_from(nanos)
+                .map_err(|_| WasiExtError::Errno(wasi::ERRNO_OVERFLOW))?;
+            Ok((nanos, set))
+        }
+    }
 }
 
-pub fn mknod(path: &str, dev: i32) -> Result<(), ExitCode> {
-    let c_path = CString::new(path).unwrap();
+// Both timestamps with the fst_flags selecting them
+fn filestat_times(
+    atime: Option<Timestamp>,
+    mtime: Option<Timestamp>,
+) -> Result<(wasi::Timestamp, wasi::Timestamp, wasi::Fstflags), WasiExtError> {
+    let (atim, atim_flags) =
+        timestamp_to_wasi(atime, wasi::FSTFLAGS_ATIM, wasi::FSTFLAGS_ATIM_NOW)?;
+    let (mtim, mtim_flags) =
+        timestamp_to_wasi(mtime, wasi::FSTFLAGS_MTIM, wasi::FSTFLAGS_MTIM_NOW)?;
+    Ok((atim, mtim, atim_flags | mtim_flags))
+}
 
-    let result = unsafe { wasi_ext_lib_generated::wasi_ext_mknod(c_path.as_ptr(), dev) };
+// None leaves the timestamp unchanged
+pub fn futimens(
+    fd: Fd,
+    atime: Option<Timestamp>,
+    mtime: Option<Timestamp>,
+) -> Result<(), WasiExtError> {
+    let (atim, mtim, fst_flags) = filestat_times(atime, mtime)?;
+    unsafe { wasi::fd_filestat_set_times(fd, atim, mtim, fst_flags) }?;
+    Ok(())
+}
 
-    if result == 0 {
-        Ok(())
-    } else {
-        Err(result)
+// Follows symlinks. The timestamps are set relative to the parent directory,
+// so unlike futimens the file does not have to be opened or even readable
+pub fn utimes<P: AsRef<Path>>(
+    path: P,
+    atime: Option<Timestamp>,
+    mtime: Option<Timestamp>,
+) -> Result<(), WasiExtError> {
+    let path = path.as_ref();
+    let (atim, mtim, fst_flags) = filestat_times(atime, mtime)?;
+    // paths without a last component are directories that are updated
+    // through themselves
+    let (parent, name) = match path.file_name() {
+        Some(name) => match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => (parent, name),
+            _ => (Path::new("."), name),
+        },
+        None => (path, OsStr::new(".")),
+    };
+    let name = name
+        .to_str()
+        .ok_or(WasiExtError::Errno(wasi::ERRNO_ILSEQ))?;
+
+    let dir = fs::File::open(parent).map_err(io_error_to_wasi)?;
+    unsafe {
+        wasi::path_filestat_set_times(
+            dir.as_raw_fd() as Fd,
+            wasi::LOOKUPFLAGS_SYMLINK_FOLLOW,
+            name,
+            atim,
+            mtim,
+            fst_flags,
+        )
+    }?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+pub enum ControlChar {
+    VINTR = termios::VINTR as usize,
+    VQUIT = termios::VQUIT as usize,
+    VERASE = termios::VERASE as usize,
+    VKILL = termios::VKILL as usize,
+    VEOF = termios::VEOF as usize,
+    VTIME = termios::VTIME as usize,
+    VMIN = termios::VMIN as usize,
+    VSWTC = termios::VSWTC as usize,
+    VSTART = termios::VSTART as usize,
+    VSTOP = termios::VSTOP as usize,
+    VSUSP = termios::VSUSP as usize,
+    VEOL = termios::VEOL as usize,
+    VREPRINT = termios::VREPRINT as usize,
+    VDISCARD = termios::VDISCARD as usize,
+    VWERASE = termios::VWERASE as usize,
+    VLNEXT = termios::VLNEXT as usize,
+    VEOL2 = termios::VEOL2 as usize,
+}
+
+// Safe accessors over the raw termios struct, conversions both ways are
+// lossless
+#[derive(Debug, Clone, Copy)]
+pub struct Termios(termios::termios);
+
+impl Termios {
+    fn lflag(&self, flag: u32) -> bool {
+        self.0.c_lflag & flag as termios::tcflag_t != 0
+    }
+
+    fn set_lflag(&mut self, flag: u32, value: bool) {
+        if value {
+            self.0.c_lflag |= flag as termios::tcflag_t;
+        } else {
+            self.0.c_lflag &= !(flag as termios::tcflag_t);
+        }
+    }
+
+    pub fn echo(&self) -> bool {
+        self.lflag(termios::ECHO)
+    }
+
+    pub fn set_echo(&mut self, echo: bool) -> &mut Self {
+        self.set_lflag(termios::ECHO, echo);
+        self
+    }
+
+    pub fn canonical(&self) -> bool {
+        self.lflag(termios::ICANON)
+    }
+
+    pub fn set_canonical(&mut self, canonical: bool) -> &mut Self {
+        self.set_lflag(termios::ICANON, canonical);
+        self
+    }
+
+    pub fn control_char(&self, cc: ControlChar) -> u8 {
+        self.0.c_cc[cc as usize]
+    }
+
+    pub fn set_control_char(&mut self, cc: ControlChar, value: u8) -> &mut Self {
+        self.0.c_cc[cc as usize] = value;
+        self
     }
 }
 
-pub fn tcgetattr(fd: Fd) -> Result<termios::termios, ExitCode> {
+impl From<termios::termios> for Termios {
+    fn from(termios_p: termios::termios) -> Self {
+        Termios(termios_p)
+    }
+}
+
+impl From<Termios> for termios::termios {
+    fn from(termios_p: Termios) -> Self {
+        termios_p.0
+    }
+}
+
+pub fn tcgetattr(fd: Fd) -> Result<termios::termios, WasiExtError> {
     let mut termios_p: termios::termios = unsafe { mem::zeroed() };
     let result = unsafe {
         termios::wasi_ext_tcgetattr(fd as c_int, &mut termios_p as *mut termios::termios)
@@ -448,7 +3629,7 @@ pub fn tcgetattr(fd: Fd) -> Result<termios::termios, ExitCode> {
     if result == 0 {
         Ok(termios_p)
     } else {
-        Err(result)
+        Err(WasiExtError::from_raw(result))
     }
 }
 
@@ -456,7 +3637,7 @@ pub fn tcsetattr(
     fd: Fd,
     act: TcsetattrAction,
     termios_p: &termios::termios,
-) -> Result<(), ExitCode> {
+) -> Result<(), WasiExtError> {
     let result = unsafe {
         termios::wasi_ext_tcsetattr(
             fd as c_int,
@@ -465,14 +3646,142 @@ pub fn tcsetattr(
         )
     };
 
+    // actions other than TCSANOW are rejected with a negated errno, the
+    // ioctl reports its failures with a positive one
     if result == 0 {
         Ok(())
     } else {
-        Err(result)
+        Err(WasiExtError::from_raw(result.abs()))
+    }
+}
+
+// The kernel does not implement TCSAFLUSH yet, fall back to TCSANOW
+fn tcsetattr_flush(fd: Fd, termios_p: &termios::termios) -> Result<(), WasiExtError> {
+    match tcsetattr(fd, TcsetattrAction::TCSAFLUSH, termios_p) {
+        Err(WasiExtError::Errno(wasi::ERRNO_NOTSUP)) => {
+            tcsetattr(fd, TcsetattrAction::TCSANOW, termios_p)
+        }
+        result => result,
+    }
+}
+
+// Switches the terminal to raw mode, the original settings are restored when
+// the guard is dropped
+pub struct RawModeGuard {
+    fd: Fd,
+    saved: termios::termios,
+    raw: termios::termios,
+    active: bool,
+}
+
+impl RawModeGuard {
+    pub fn new(fd: Fd) -> Result<Self, WasiExtError> {
+        let saved = tcgetattr(fd)?;
+        let mut raw = saved;
+        cfmakeraw(&mut raw);
+        tcsetattr(fd, TcsetattrAction::TCSANOW, &raw)?;
+        Ok(RawModeGuard {
+            fd,
+            saved,
+            raw,
+            active: true,
+        })
+    }
+
+    pub fn saved(&self) -> &termios::termios {
+        &self.saved
+    }
+
+    // Temporarily restores the original settings, e.g. before running a
+    // foreground job
+    pub fn suspend(&mut self) -> Result<(), WasiExtError> {
+        if self.active {
+            tcsetattr_flush(self.fd, &self.saved)?;
+            self.active = false;
+        }
+        Ok(())
+    }
+
+    pub fn resume(&mut self) -> Result<(), WasiExtError> {
+        if !self.active {
+            tcsetattr(self.fd, TcsetattrAction::TCSANOW, &self.raw)?;
+            self.active = true;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RawModeGuard {
+    fn drop(&mut self) {
+        let _ = self.suspend();
+    }
+}
+
+// Typed view of termios::winsize, pixel sizes are 0 when unknown. Setters
+// return &mut Self so a size can be built in place before tcsetwinsize
+#[derive(Debug, Clone, Copy)]
+pub struct Winsize(termios::winsize);
+
+impl Winsize {
+    pub fn new(rows: u16, cols: u16) -> Self {
+        Winsize(termios::winsize {
+            ws_row: rows,
+            ws_col: cols,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        })
+    }
+
+    pub fn rows(&self) -> u16 {
+        self.0.ws_row
+    }
+
+    pub fn set_rows(&mut self, rows: u16) -> &mut Self {
+        self.0.ws_row = rows;
+        self
+    }
+
+    pub fn cols(&self) -> u16 {
+        self.0.ws_col
+    }
+
+    pub fn set_cols(&mut self, cols: u16) -> &mut Self {
+        self.0.ws_col = cols;
+        self
+    }
+
+    pub fn pixel_width(&self) -> u16 {
+        self.0.ws_xpixel
+    }
+
+    pub fn set_pixel_width(&mut self, width: u16) -> &mut Self {
+        self.0.ws_xpixel = width;
+        self
+    }
+
+    pub fn pixel_height(&self) -> u16 {
+        self.0.ws_ypixel
+    }
+
+    pub fn set_pixel_height(&mut self, height: u16) -> &mut Self {
+        self.0.ws_ypixel = height;
+        self
+    }
+}
+
+impl From<termios::winsize> for Winsize {
+    fn from(winsize: termios::winsize) -> Self {
+        Winsize(winsize)
     }
 }
 
-pub fn tcgetwinsize(fd: Fd) -> Result<termios::winsize, ExitCode> {
+impl From<Winsize> for termios::winsize {
+    fn from(winsize: Winsize) -> Self {
+        winsize.0
+    }
+}
+
+pub fn tcgetwinsize(fd: Fd) -> Result<termios::winsize, WasiExtError> {
     let mut winsize: termios::winsize = unsafe { mem::zeroed() };
 
     let result = unsafe {
@@ -482,10 +3791,290 @@ pub fn tcgetwinsize(fd: Fd) -> Result<termios::winsize, ExitCode> {
     if result == 0 {
         Ok(winsize)
     } else {
-        Err(result)
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+pub fn tcsetwinsize(fd: Fd, winsize: &termios::winsize) -> Result<(), WasiExtError> {
+    let result =
+        unsafe { termios::wasi_ext_tcsetwinsize(fd as c_int, winsize as *const termios::winsize) };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+// Session that has the terminal as its controlling terminal. Non terminals
+// fail with ENOTTY, terminals without a session with ENXIO and kernels that
+// do not model sessions with ENOSYS
+pub fn tcgetsid(fd: Fd) -> Result<Pid, WasiExtError> {
+    let result = unsafe { termios::wasi_ext_tcgetsid(fd as c_int) };
+
+    if result < 0 {
+        Err(WasiExtError::from_raw(-result))
+    } else {
+        Ok(Pid::from_raw(result))
+    }
+}
+
+pub fn tcgetpgrp(fd: Fd) -> Result<Pid, WasiExtError> {
+    let result = unsafe { termios::wasi_ext_tcgetpgrp(fd as c_int) };
+
+    if result < 0 {
+        Err(WasiExtError::from_raw(-result))
+    } else {
+        Ok(Pid::from_raw(result))
+    }
+}
+
+// The kernel rejects fds that are not terminals with ENOTTY
+pub fn tcsetpgrp(fd: Fd, pgrp: Pid) -> Result<(), WasiExtError> {
+    let result = unsafe { termios::wasi_ext_tcsetpgrp(fd as c_int, pgrp.into_raw()) };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+// Both fail with ENOTTY if the fd is not a terminal
+pub fn tcflush(fd: Fd, queue: FlushQueue) -> Result<(), WasiExtError> {
+    let result = unsafe { termios::wasi_ext_tcflush(fd as c_int, queue as c_int) };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+pub fn tcflow(fd: Fd, action: FlowAction) -> Result<(), WasiExtError> {
+    let result = unsafe { termios::wasi_ext_tcflow(fd as c_int, action as c_int) };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+// POSIX leaves the length of a break for nonzero durations up to the
+// implementation, like Linux the duration is ignored and a break of the
+// default length is requested. Terminals that do not model breaks only drain
+// their output, non terminals fail with ENOTTY
+pub fn tcsendbreak(fd: Fd, duration: i32) -> Result<(), WasiExtError> {
+    let result = unsafe { termios::wasi_ext_tcsendbreak(fd as c_int, duration) };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(WasiExtError::from_raw(result))
+    }
+}
+
+// Blocks until all output written to the fd has been transmitted, fails with
+// EINTR if interrupted by a signal
+pub fn tcdrain(fd: Fd) -> Result<(), WasiExtError> {
+    let result = unsafe { termios::wasi_ext_tcdrain(fd as c_int) };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(WasiExtError::from_raw(result))
     }
 }
 
 pub fn cfmakeraw(termios_p: &mut termios::termios) {
     unsafe { termios::wasi_ext_cfmakeraw(termios_p as *mut termios::termios) };
 }
+
+pub fn cfgetispeed(termios_p: &termios::termios) -> termios::speed_t {
+    unsafe { termios::wasi_ext_cfgetispeed(termios_p as *const termios::termios) }
+}
+
+pub fn cfgetospeed(termios_p: &termios::termios) -> termios::speed_t {
+    unsafe { termios::wasi_ext_cfgetospeed(termios_p as *const termios::termios) }
+}
+
+pub fn cfsetispeed(
+    termios_p: &mut termios::termios,
+    speed: termios::speed_t,
+) -> Result<(), WasiExtError> {
+    let result =
+        unsafe { termios::wasi_ext_cfsetispeed(termios_p as *mut termios::termios, speed) };
+
+    if result < 0 {
+        Err(WasiExtError::from_raw(-result))
+    } else {
+        Ok(())
+    }
+}
+
+pub fn cfsetospeed(
+    termios_p: &mut termios::termios,
+    speed: termios::speed_t,
+) -> Result<(), WasiExtError> {
+    let result =
+        unsafe { termios::wasi_ext_cfsetospeed(termios_p as *mut termios::termios, speed) };
+
+    if result < 0 {
+        Err(WasiExtError::from_raw(-result))
+    } else {
+        Ok(())
+    }
+}
+
+// Single entry point for terminal programs, raw mode is left and the event fd
+// is closed when the terminal is dropped
+pub struct Terminal {
+    fd: Fd,
+    raw_mode: Option<RawModeGuard>,
+    #[cfg(feature = "hterm")]
+    event_fd: Option<RawFd>,
+}
+
+impl Terminal {
+    // Fails with ENOTTY if the fd is not a terminal
+    pub fn new(fd: Fd) -> Result<Self, WasiExtError> {
+        if !isatty(fd as i32)? {
+            return Err(WasiExtError::Errno(wasi::ERRNO_NOTTY));
+        }
+        Ok(Terminal {
+            fd,
+            raw_mode: None,
+            #[cfg(feature = "hterm")]
+            event_fd: None,
+        })
+    }
+
+    pub fn fd(&self) -> Fd {
+        self.fd
+    }
+
+    pub fn size(&self) -> Result<termios::winsize, WasiExtError> {
+        tcgetwinsize(self.fd)
+    }
+
+    pub fn is_raw(&self) -> bool {
+        self.raw_mode.is_some()
+    }
+
+    pub fn enter_raw(&mut self) -> Result<(), WasiExtError> {
+        if self.raw_mode.is_none() {
+            self.raw_mode = Some(RawModeGuard::new(self.fd)?);
+        }
+        Ok(())
+    }
+
+    pub fn leave_raw(&mut self) -> Result<(), WasiExtError> {
+        if let Some(guard) = self.raw_mode.as_mut() {
+            guard.suspend()?;
+            self.raw_mode = None;
+        }
+        Ok(())
+    }
+
+    // The event fd is created on first use and shared by all streams
+    #[cfg(feature = "hterm")]
+    pub fn events(&mut self) -> Result<EventStream, WasiExtError> {
+        let fd = match self.event_fd {
+            Some(fd) => fd,
+            None => *self
+                .event_fd
+                .insert(event_source_fd(EventMask::WINCH | EventMask::SIGINT)?),
+        };
+        Ok(EventStream::new(fd))
+    }
+}
+
+#[cfg(feature = "hterm")]
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if let Some(fd) = self.event_fd {
+            let _ = close(fd as Fd);
+        }
+    }
+}
+
+// Owned copies of the generated termios structs that can be serialized, e.g.
+// to save and restore terminal settings across sessions
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct TermiosState {
+    pub c_iflag: termios::tcflag_t,
+    pub c_oflag: termios::tcflag_t,
+    pub c_cflag: termios::tcflag_t,
+    pub c_lflag: termios::tcflag_t,
+    pub c_line: termios::cc_t,
+    pub c_cc: [termios::cc_t; termios::NCCS as usize],
+    pub ispeed: termios::speed_t,
+    pub ospeed: termios::speed_t,
+}
+
+#[cfg(feature = "serde")]
+impl From<termios::termios> for TermiosState {
+    fn from(termios_p: termios::termios) -> Self {
+        TermiosState {
+            c_iflag: termios_p.c_iflag,
+            c_oflag: termios_p.c_oflag,
+            c_cflag: termios_p.c_cflag,
+            c_lflag: termios_p.c_lflag,
+            c_line: termios_p.c_line,
+            c_cc: termios_p.c_cc,
+            ispeed: termios_p.__c_ispeed,
+            ospeed: termios_p.__c_ospeed,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<TermiosState> for termios::termios {
+    fn from(state: TermiosState) -> Self {
+        termios::termios {
+            c_iflag: state.c_iflag,
+            c_oflag: state.c_oflag,
+            c_cflag: state.c_cflag,
+            c_lflag: state.c_lflag,
+            c_line: state.c_line,
+            c_cc: state.c_cc,
+            __c_ispeed: state.ispeed,
+            __c_ospeed: state.ospeed,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct WinsizeState {
+    pub ws_row: u16,
+    pub ws_col: u16,
+    pub ws_xpixel: u16,
+    pub ws_ypixel: u16,
+}
+
+#[cfg(feature = "serde")]
+impl From<termios::winsize> for WinsizeState {
+    fn from(winsize: termios::winsize) -> Self {
+        WinsizeState {
+            ws_row: winsize.ws_row,
+            ws_col: winsize.ws_col,
+            ws_xpixel: winsize.ws_xpixel,
+            ws_ypixel: winsize.ws_ypixel,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<WinsizeState> for termios::winsize {
+    fn from(state: WinsizeState) -> Self {
+        termios::winsize {
+            ws_row: state.ws_row,
+            ws_col: state.ws_col,
+            ws_xpixel: state.ws_xpixel,
+            ws_ypixel: state.ws_ypixel,
+        }
+    }
+}
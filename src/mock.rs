@@ -0,0 +1,1781 @@
+/*
+ * Copyright (c) 2022-2023 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+// Host implementations of the C library for the mock feature. Every function
+// keeps the signature and the return convention of its counterpart in c_lib,
+// errors are translated to WASI errno and signals to WASI numbering. Calls
+// the host cannot give a meaningful answer to fail with ENOSYS, mounts are
+// only tracked in this process
+
+use std::env;
+use std::ffi::{c_char, c_int, c_uint, c_void, CStr, CString, OsStr};
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process;
+use std::ptr;
+use std::slice;
+use std::sync::{Mutex, PoisonError};
+
+use crate::termios::{self, pid_t, speed_t, winsize};
+use crate::wasi_ext_lib_generated::{
+    Env, FcntlCommand, FcntlCommand_F_DUPFD, FcntlCommand_F_GETFD, FcntlCommand_F_GETFL,
+    FcntlCommand_F_GETLK, FcntlCommand_F_MVFD, FcntlCommand_F_SETFD, FcntlCommand_F_SETFL,
+    FcntlCommand_F_SETLK, FcntlCommand_F_SETLKW, Flock, Redirect, RedirectType_APPEND,
+    RedirectType_CLOSE, RedirectType_DUPLICATE, RedirectType_PIPEIN, RedirectType_PIPEOUT,
+    RedirectType_READ, RedirectType_READWRITE, RedirectType_READWRITEAPPEND, RedirectType_WRITE,
+    Rlimit, Stat, StatVfs, _MAX_FD_NUM, CLOSE_RANGE_CLOEXEC, F_RDLCK, F_UNLCK, F_WRLCK, RLIMIT_AS,
+    RLIMIT_CPU, RLIMIT_NOFILE, WCONTINUED, WCONTINUED_STATUS, WNOHANG, WSTOPPED_MARK, WUNTRACED,
+};
+use crate::{EXIT_SIGNAL_BASE, RLIM_INFINITY, WASI_EXT_FDFLAG_CLOEXEC};
+
+const ENOSYS: c_int = wasi::ERRNO_NOSYS.raw() as c_int;
+
+fn errno_raw(errno: wasi::Errno) -> c_int {
+    errno.raw() as c_int
+}
+
+fn wasi_errno(err: &io::Error) -> wasi::Errno {
+    match err.raw_os_error() {
+        Some(0) => wasi::ERRNO_SUCCESS,
+        Some(libc::E2BIG) => wasi::ERRNO_2BIG,
+        Some(libc::EACCES) => wasi::ERRNO_ACCES,
+        Some(libc::EADDRINUSE) => wasi::ERRNO_ADDRINUSE,
+        Some(libc::EADDRNOTAVAIL) => wasi::ERRNO_ADDRNOTAVAIL,
+        Some(libc::EAGAIN) => wasi::ERRNO_AGAIN,
+        Some(libc::EALREADY) => wasi::ERRNO_ALREADY,
+        Some(libc::EBADF) => wasi::ERRNO_BADF,
+        Some(libc::EBUSY) => wasi::ERRNO_BUSY,
+        Some(libc::ECHILD) => wasi::ERRNO_CHILD,
+        Some(libc::ECONNABORTED) => wasi::ERRNO_CONNABORTED,
+        Some(libc::ECONNREFUSED) => wasi::ERRNO_CONNREFUSED,
+        Some(libc::ECONNRESET) => wasi::ERRNO_CONNRESET,
+        Some(libc::EDEADLK) => wasi::ERRNO_DEADLK,
+        Some(libc::EEXIST) => wasi::ERRNO_EXIST,
+        Some(libc::EFAULT) => wasi::ERRNO_FAULT,
+        Some(libc::EFBIG) => wasi::ERRNO_FBIG,
+        Some(libc::EILSEQ) => wasi::ERRNO_ILSEQ,
+        Some(libc::EINTR) => wasi::ERRNO_INTR,
+        Some(libc::EINVAL) => wasi::ERRNO_INVAL,
+        Some(libc::EIO) => wasi::ERRNO_IO,
+        Some(libc::EISDIR) => wasi::ERRNO_ISDIR,
+        Some(libc::ELOOP) => wasi::ERRNO_LOOP,
+        Some(libc::EMFILE) => wasi::ERRNO_MFILE,
+        Some(libc::EMLINK) => wasi::ERRNO_MLINK,
+        Some(libc::ENAMETOOLONG) => wasi::ERRNO_NAMETOOLONG,
+        Some(libc::ENFILE) => wasi::ERRNO_NFILE,
+        Some(libc::ENODEV) => wasi::ERRNO_NODEV,
+        Some(libc::ENOENT) => wasi::ERRNO_NOENT,
+        Some(libc::ENOEXEC) => wasi::ERRNO_NOEXEC,
+        Some(libc::ENOLCK) => wasi::ERRNO_NOLCK,
+        Some(libc::ENOMEM) => wasi::ERRNO_NOMEM,
+        Some(libc::ENOSPC) => wasi::ERRNO_NOSPC,
+        Some(libc::ENOSYS) => wasi::ERRNO_NOSYS,
+        Some(libc::ENOTDIR) => wasi::ERRNO_NOTDIR,
+        Some(libc::ENOTEMPTY) => wasi::ERRNO_NOTEMPTY,
+        Some(libc::ENOTSOCK) => wasi::ERRNO_NOTSOCK,
+        Some(libc::ENOTSUP) => wasi::ERRNO_NOTSUP,
+        Some(libc::ENOTTY) => wasi::ERRNO_NOTTY,
+        Some(libc::ENXIO) => wasi::ERRNO_NXIO,
+        Some(libc::EOVERFLOW) => wasi::ERRNO_OVERFLOW,
+        Some(libc::EPERM) => wasi::ERRNO_PERM,
+        Some(libc::EPIPE) => wasi::ERRNO_PIPE,
+        Some(libc::ERANGE) => wasi::ERRNO_RANGE,
+        Some(libc::EROFS) => wasi::ERRNO_ROFS,
+        Some(libc::ESPIPE) => wasi::ERRNO_SPIPE,
+        Some(libc::ESRCH) => wasi::ERRNO_SRCH,
+        Some(libc::ETIMEDOUT) => wasi::ERRNO_TIMEDOUT,
+        Some(libc::EXDEV) => wasi::ERRNO_XDEV,
+        _ => wasi::ERRNO_IO,
+    }
+}
+
+pub(crate) fn host_errno(err: &io::Error) -> c_int {
+    errno_raw(wasi_errno(err))
+}
+
+fn last_errno() -> c_int {
+    host_errno(&io::Error::last_os_error())
+}
+
+// For functions returning a positive errno
+fn check(result: c_int) -> c_int {
+    if result < 0 {
+        last_errno()
+    } else {
+        0
+    }
+}
+
+// For functions returning a value or a negative errno
+fn check_value(result: c_int) -> c_int {
+    if result < 0 {
+        -last_errno()
+    } else {
+        result
+    }
+}
+
+unsafe fn os_str<'a>(s: *const c_char) -> &'a OsStr {
+    OsStr::from_bytes(CStr::from_ptr(s).to_bytes())
+}
+
+// Copies the output with a NUL terminator, ENOBUFS if it does not fit so
+// grow_buf_until_fits retries with a bigger buffer
+unsafe fn write_buf(output: &[u8], buf: *mut c_char, buf_len: usize) -> c_int {
+    if output.len() >= buf_len {
+        return errno_raw(wasi::ERRNO_NOBUFS);
+    }
+    ptr::copy_nonoverlapping(output.as_ptr(), buf as *mut u8, output.len());
+    *buf.add(output.len()) = 0;
+    0
+}
+
+const SIGNALS: [(wasi::Signal, c_int); 28] = [
+    (wasi::SIGNAL_HUP, libc::SIGHUP),
+    (wasi::SIGNAL_INT, libc::SIGINT),
+    (wasi::SIGNAL_QUIT, libc::SIGQUIT),
+    (wasi::SIGNAL_ILL, libc::SIGILL),
+    (wasi::SIGNAL_TRAP, libc::SIGTRAP),
+    (wasi::SIGNAL_ABRT, libc::SIGABRT),
+    (wasi::SIGNAL_BUS, libc::SIGBUS),
+    (wasi::SIGNAL_FPE, libc::SIGFPE),
+    (wasi::SIGNAL_KILL, libc::SIGKILL),
+    (wasi::SIGNAL_USR1, libc::SIGUSR1),
+    (wasi::SIGNAL_SEGV, libc::SIGSEGV),
+    (wasi::SIGNAL_USR2, libc::SIGUSR2),
+    (wasi::SIGNAL_PIPE, libc::SIGPIPE),
+    (wasi::SIGNAL_ALRM, libc::SIGALRM),
+    (wasi::SIGNAL_TERM, libc::SIGTERM),
+    (wasi::SIGNAL_CHLD, libc::SIGCHLD),
+    (wasi::SIGNAL_CONT, libc::SIGCONT),
+    (wasi::SIGNAL_STOP, libc::SIGSTOP),
+    (wasi::SIGNAL_TSTP, libc::SIGTSTP),
+    (wasi::SIGNAL_TTIN, libc::SIGTTIN),
+    (wasi::SIGNAL_TTOU, libc::SIGTTOU),
+    (wasi::SIGNAL_URG, libc::SIGURG),
+    (wasi::SIGNAL_XCPU, libc::SIGXCPU),
+    (wasi::SIGNAL_XFSZ, libc::SIGXFSZ),
+    (wasi::SIGNAL_VTALRM, libc::SIGVTALRM),
+    (wasi::SIGNAL_PROF, libc::SIGPROF),
+    (wasi::SIGNAL_WINCH, libc::SIGWINCH),
+    (wasi::SIGNAL_SYS, libc::SIGSYS),
+];
+
+fn host_signal(signal: c_int) -> Option<c_int> {
+    if signal == 0 {
+        return Some(0);
+    }
+    SIGNALS
+        .iter()
+        .find(|(wasi_signal, _)| wasi_signal.raw() as c_int == signal)
+        .map(|(_, host_signal)| *host_signal)
+}
+
+// Host signals without a WASI counterpart are reported as SIGKILL
+fn wasi_signal(signal: c_int) -> c_int {
+    SIGNALS
+        .iter()
+        .find(|(_, host_signal)| *host_signal == signal)
+        .map_or(wasi::SIGNAL_KILL, |(wasi_signal, _)| *wasi_signal)
+        .raw() as c_int
+}
+
+// Encodes a host wait status the way the kernel does, see WaitStatus
+fn wait_status(status: c_int) -> c_int {
+    if libc::WIFEXITED(status) {
+        libc::WEXITSTATUS(status)
+    } else if libc::WIFSIGNALED(status) {
+        EXIT_SIGNAL_BASE + wasi_signal(libc::WTERMSIG(status))
+    } else if libc::WIFSTOPPED(status) {
+        wasi_signal(libc::WSTOPSIG(status)) << 8 | WSTOPPED_MARK as c_int
+    } else {
+        WCONTINUED_STATUS as c_int
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_ext_errno() -> c_int {
+    last_errno()
+}
+
+// Mount points are only recorded here, the host filesystem is left alone.
+// Targets are compared after canonicalization
+static MOUNTS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+unsafe fn mount_point(path: *const c_char) -> Result<PathBuf, c_int> {
+    let path = fs::canonicalize(os_str(path)).map_err(|e| host_errno(&e))?;
+    if !path.is_dir() {
+        return Err(errno_raw(wasi::ERRNO_NOTDIR));
+    }
+    Ok(path)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_mount(
+    _source_fd: c_int,
+    _source_path: *const c_char,
+    _target_fd: c_int,
+    target_path: *const c_char,
+    _filesystem_type: *const c_char,
+    _mount_flags: u64,
+    _data: *const c_char,
+) -> c_int {
+    let target = match mount_point(target_path) {
+        Ok(target) => target,
+        Err(e) => return e,
+    };
+    let mut mounts = MOUNTS.lock().unwrap_or_else(PoisonError::into_inner);
+    if mounts.contains(&target) {
+        return errno_raw(wasi::ERRNO_BUSY);
+    }
+    mounts.push(target);
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_umount(path: *const c_char) -> c_int {
+    let target = match mount_point(path) {
+        Ok(target) => target,
+        Err(e) => return e,
+    };
+    let mut mounts = MOUNTS.lock().unwrap_or_else(PoisonError::into_inner);
+    match mounts.iter().position(|mount| *mount == target) {
+        Some(i) => {
+            mounts.remove(i);
+            0
+        }
+        None => errno_raw(wasi::ERRNO_INVAL),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_chdir(path: *const c_char) -> c_int {
+    match env::set_current_dir(os_str(path)) {
+        Ok(()) => 0,
+        Err(e) => host_errno(&e),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_ext_fchdir(fd: c_int) -> c_int {
+    check(unsafe { libc::fchdir(fd) })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_getcwd(path: *mut c_char, buf_len: usize) -> c_int {
+    match env::current_dir() {
+        Ok(cwd) => write_buf(cwd.as_os_str().as_bytes(), path, buf_len),
+        Err(e) => host_errno(&e),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_gethostname(name: *mut c_char, buf_len: usize) -> c_int {
+    let mut host_name = [0u8; 256];
+    if libc::gethostname(host_name.as_mut_ptr() as *mut c_char, host_name.len()) < 0 {
+        return last_errno();
+    }
+    let len = host_name
+        .iter()
+        .position(|&i| i == 0)
+        .unwrap_or(host_name.len());
+    write_buf(&host_name[..len], name, buf_len)
+}
+
+// The host name is shared with the rest of the system, it is never changed
+#[no_mangle]
+pub extern "C" fn wasi_ext_sethostname(_name: *const c_char) -> c_int {
+    ENOSYS
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_ext_isatty(fd: c_int) -> c_int {
+    if unsafe { libc::isatty(fd) } == 1 {
+        return 1;
+    }
+    match io::Error::last_os_error().raw_os_error() {
+        Some(libc::EBADF) => -errno_raw(wasi::ERRNO_BADF),
+        _ => 0,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_ttyname(fd: c_int, buf: *mut c_char, buf_len: usize) -> c_int {
+    let mut name = [0 as c_char; 256];
+    match libc::ttyname_r(fd, name.as_mut_ptr(), name.len()) {
+        0 => write_buf(CStr::from_ptr(name.as_ptr()).to_bytes(), buf, buf_len),
+        e => host_errno(&io::Error::from_raw_os_error(e)),
+    }
+}
+
+// std::env panics on keys it cannot store, they are rejected up front
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_set_env(attrib: *const c_char, val: *const c_char) -> c_int {
+    let key = os_str(attrib);
+    if key.is_empty() || key.as_bytes().contains(&b'=') {
+        return errno_raw(wasi::ERRNO_INVAL);
+    }
+    if val.is_null() {
+        env::remove_var(key);
+    } else {
+        env::set_var(key, os_str(val));
+    }
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_get_env(
+    attrib: *const c_char,
+    val: *mut c_char,
+    buf_len: usize,
+) -> c_int {
+    match env::var_os(os_str(attrib)) {
+        Some(value) => write_buf(value.as_bytes(), val, buf_len),
+        None => errno_raw(wasi::ERRNO_NOENT),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_environ(buf: *mut c_char, buf_len: usize) -> c_int {
+    let mut output = Vec::new();
+    for (key, val) in env::vars_os() {
+        output.extend_from_slice(key.as_bytes());
+        output.push(b'=');
+        output.extend_from_slice(val.as_bytes());
+        output.push(0);
+    }
+    // the terminator of the last entry is followed by the empty one
+    write_buf(&output, buf, buf_len)
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_ext_getpid() -> c_int {
+    unsafe { libc::getpid() }
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_ext_getppid() -> c_int {
+    unsafe { libc::getppid() }
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_ext_getpgid(pid: c_int) -> c_int {
+    check_value(unsafe { libc::getpgid(pid) })
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_ext_setpgid(pid: c_int, pgid: c_int) -> c_int {
+    -check(unsafe { libc::setpgid(pid, pgid) })
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_ext_getsid(pid: c_int) -> c_int {
+    check_value(unsafe { libc::getsid(pid) })
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_ext_setsid() -> c_int {
+    check_value(unsafe { libc::setsid() })
+}
+
+#[cfg(feature = "hterm")]
+#[no_mangle]
+pub extern "C" fn wasi_ext_event_source_fd(_event_mask: u32) -> c_int {
+    -ENOSYS
+}
+
+#[cfg(feature = "hterm")]
+#[no_mangle]
+pub extern "C" fn wasi_ext_attach_sigint(_fd: i32) -> c_int {
+    -ENOSYS
+}
+
+#[cfg(feature = "hterm")]
+#[no_mangle]
+pub extern "C" fn wasi_ext_attach_signal(_fd: i32, _signal: c_int) -> c_int {
+    -ENOSYS
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_ext_clean_inodes() -> c_int {
+    ENOSYS
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_ext_clean_inodes_report(_reclaimed: *mut c_int) -> c_int {
+    ENOSYS
+}
+
+enum RedirectAction {
+    Open(c_int, CString, c_int),
+    Duplicate(c_int, c_int),
+    Close(c_int),
+}
+
+unsafe fn redirect_action(redirect: &Redirect) -> Result<RedirectAction, c_int> {
+    let open_flags = match redirect.type_ {
+        RedirectType_READ => libc::O_RDONLY,
+        RedirectType_WRITE => libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC,
+        RedirectType_APPEND => libc::O_WRONLY | libc::O_CREAT | libc::O_APPEND,
+        RedirectType_READWRITE => libc::O_RDWR | libc::O_CREAT,
+        RedirectType_READWRITEAPPEND => libc::O_RDWR | libc::O_CREAT | libc::O_APPEND,
+        RedirectType_PIPEIN | RedirectType_PIPEOUT | RedirectType_DUPLICATE => {
+            return Ok(RedirectAction::Duplicate(
+                redirect.data.fd_src,
+                redirect.fd_dst,
+            ));
+        }
+        RedirectType_CLOSE => return Ok(RedirectAction::Close(redirect.fd_dst)),
+        _ => return Err(errno_raw(wasi::ERRNO_INVAL)),
+    };
+    let path = slice::from_raw_parts(
+        redirect.data.path.path_str as *const u8,
+        redirect.data.path.path_len,
+    );
+    let path = CString::new(path).map_err(|_| errno_raw(wasi::ERRNO_INVAL))?;
+    Ok(RedirectAction::Open(redirect.fd_dst, path, open_flags))
+}
+
+// Runs in the forked child, so only async-signal-safe calls are made
+fn apply_redirects(actions: &[RedirectAction]) -> io::Result<()> {
+    for action in actions {
+        let result = match action {
+            RedirectAction::Open(fd, path, flags) => unsafe {
+                let opened = libc::open(path.as_ptr(), *flags, 0o666 as c_uint);
+                if opened < 0 || opened == *fd {
+                    opened
+                } else {
+                    let result = libc::dup2(opened, *fd);
+                    libc::close(opened);
+                    result
+                }
+            },
+            // dup2 keeps the close-on-exec flag when both fds are the same
+            RedirectAction::Duplicate(fd_src, fd_dst) if fd_src == fd_dst => unsafe {
+                libc::fcntl(*fd_dst, libc::F_SETFD, 0)
+            },
+            RedirectAction::Duplicate(fd_src, fd_dst) => unsafe { libc::dup2(*fd_src, *fd_dst) },
+            RedirectAction::Close(fd) => unsafe {
+                libc::close(*fd);
+                0
+            },
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+// The child inherits the host environment extended with the given variables,
+// the program is looked up in PATH if it has no slash
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_spawn(
+    path: *const c_char,
+    args: *const *const c_char,
+    n_args: usize,
+    env: *const Env,
+    n_env: usize,
+    background: c_int,
+    redirects: *const Redirect,
+    n_redirects: usize,
+    child_pid: *mut c_int,
+) -> c_int {
+    let mut command = process::Command::new(os_str(path));
+    if n_args > 0 {
+        for arg in slice::from_raw_parts(args, n_args) {
+            command.arg(os_str(*arg));
+        }
+    }
+    if n_env > 0 {
+        for var in slice::from_raw_parts(env, n_env) {
+            command.env(os_str(var.attrib), os_str(var.val));
+        }
+    }
+
+    let mut actions = Vec::new();
+    if n_redirects > 0 {
+        for redirect in slice::from_raw_parts(redirects, n_redirects) {
+            match redirect_action(redirect) {
+                Ok(action) => actions.push(action),
+                Err(e) => return -e,
+            }
+        }
+    }
+    command.pre_exec(move || apply_redirects(&actions));
+
+    let child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => return -host_errno(&e),
+    };
+    let pid = child.id() as c_int;
+    *child_pid = pid;
+    if background != 0 {
+        return 0;
+    }
+
+    let mut status: c_int = 0;
+    if libc::waitpid(pid, &mut status, 0) < 0 {
+        return -last_errno();
+    }
+    wait_status(status)
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_ext_kill(pid: c_int, sig: c_int) -> c_int {
+    match host_signal(sig) {
+        Some(signal) => -check(unsafe { libc::kill(pid, signal) }),
+        None => -errno_raw(wasi::ERRNO_INVAL),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_waitpid(pid: c_int, options: c_int, status: *mut c_int) -> c_int {
+    let mut host_options = 0;
+    if options & WNOHANG as c_int != 0 {
+        host_options |= libc::WNOHANG;
+    }
+    if options & WUNTRACED as c_int != 0 {
+        host_options |= libc::WUNTRACED;
+    }
+    if options & WCONTINUED as c_int != 0 {
+        host_options |= libc::WCONTINUED;
+    }
+
+    let mut host_status: c_int = 0;
+    let result = libc::waitpid(pid, &mut host_status, host_options);
+    if result < 0 {
+        return -last_errno();
+    }
+    if result > 0 {
+        *status = wait_status(host_status);
+    }
+    result
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_ext_renice(_pid: c_int, _delta: c_int, _priority: *mut c_int) -> c_int {
+    -ENOSYS
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_ext_sigprocmask(_how: c_int, _set: u32, _oldset: *mut u32) -> c_int {
+    -ENOSYS
+}
+
+// Linux shares the termios layout and flag values with the kernel ABI the
+// bindings follow, other hosts only get window size and process group calls
+#[cfg(target_os = "linux")]
+unsafe fn termios_ioctl(fd: c_int, cmd: c_uint, arg: *mut c_void) -> Option<c_int> {
+    let tio = &mut *(arg as *mut termios::termios);
+    let mut host: libc::termios = mem::zeroed();
+    match cmd {
+        termios::TCGETS => {
+            if libc::tcgetattr(fd, &mut host) < 0 {
+                return Some(-last_errno());
+            }
+            tio.c_iflag = host.c_iflag;
+            tio.c_oflag = host.c_oflag;
+            tio.c_cflag = host.c_cflag;
+            tio.c_lflag = host.c_lflag;
+            tio.c_line = host.c_line;
+            let n = host.c_cc.len().min(tio.c_cc.len());
+            tio.c_cc[..n].copy_from_slice(&host.c_cc[..n]);
+            Some(0)
+        }
+        termios::TCSETS => {
+            if libc::tcgetattr(fd, &mut host) < 0 {
+                return Some(-last_errno());
+            }
+            host.c_iflag = tio.c_iflag;
+            host.c_oflag = tio.c_oflag;
+            host.c_cflag = tio.c_cflag;
+            host.c_lflag = tio.c_lflag;
+            host.c_line = tio.c_line;
+            let n = host.c_cc.len().min(tio.c_cc.len());
+            host.c_cc[..n].copy_from_slice(&tio.c_cc[..n]);
+            Some(-check(libc::tcsetattr(fd, libc::TCSANOW, &host)))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+unsafe fn termios_ioctl(_fd: c_int, _cmd: c_uint, _arg: *mut c_void) -> Option<c_int> {
+    None
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_ioctl(fd: c_int, cmd: c_uint, arg: *mut c_void) -> c_int {
+    if libc::isatty(fd) != 1 {
+        return -last_errno();
+    }
+    if let Some(result) = termios_ioctl(fd, cmd, arg) {
+        return result;
+    }
+    match cmd {
+        termios::TIOCGWINSZ => {
+            let mut host: libc::winsize = mem::zeroed();
+            if libc::ioctl(fd, libc::TIOCGWINSZ, &mut host) < 0 {
+                return -last_errno();
+            }
+            *(arg as *mut winsize) = winsize {
+                ws_row: host.ws_row,
+                ws_col: host.ws_col,
+                ws_xpixel: host.ws_xpixel,
+                ws_ypixel: host.ws_ypixel,
+            };
+            0
+        }
+        termios::TIOCSWINSZ => {
+            let size = *(arg as *const winsize);
+            let host = libc::winsize {
+                ws_row: size.ws_row,
+                ws_col: size.ws_col,
+                ws_xpixel: size.ws_xpixel,
+                ws_ypixel: size.ws_ypixel,
+            };
+            -check(libc::ioctl(fd, libc::TIOCSWINSZ, &host))
+        }
+        termios::TIOCGPGRP | termios::TIOCGSID => {
+            let result = if cmd == termios::TIOCGPGRP {
+                libc::tcgetpgrp(fd)
+            } else {
+                libc::tcgetsid(fd)
+            };
+            if result < 0 {
+                return -last_errno();
+            }
+            *(arg as *mut pid_t) = result;
+            0
+        }
+        termios::TIOCSPGRP => -check(libc::tcsetpgrp(fd, *(arg as *const pid_t))),
+        _ => -ENOSYS,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_ext_ioctl_buf(
+    _fd: c_int,
+    _cmd: c_uint,
+    _buf: *mut c_void,
+    _buf_len: usize,
+) -> c_int {
+    -ENOSYS
+}
+
+fn host_fdflags_to_wasi(flags: c_int) -> wasi::Fdflags {
+    let mut fdflags = 0;
+    if flags & libc::O_APPEND != 0 {
+        fdflags |= wasi::FDFLAGS_APPEND;
+    }
+    if flags & libc::O_NONBLOCK != 0 {
+        fdflags |= wasi::FDFLAGS_NONBLOCK;
+    }
+    if flags & libc::O_DSYNC != 0 {
+        fdflags |= wasi::FDFLAGS_DSYNC;
+    }
+    if flags & libc::O_SYNC != 0 {
+        fdflags |= wasi::FDFLAGS_SYNC;
+    }
+    fdflags
+}
+
+unsafe fn fcntl_lock(fd: c_int, cmd: FcntlCommand, lock: *mut Flock) -> c_int {
+    let mut host: libc::flock = mem::zeroed();
+    host.l_type = match (*lock).type_ as u32 {
+        F_RDLCK => libc::F_RDLCK,
+        F_WRLCK => libc::F_WRLCK,
+        F_UNLCK => libc::F_UNLCK,
+        _ => return -errno_raw(wasi::ERRNO_INVAL),
+    } as _;
+    // __wasi_whence_t values are the same as SEEK_SET, SEEK_CUR and SEEK_END
+    host.l_whence = (*lock).whence as _;
+    host.l_start = (*lock).start as _;
+    host.l_len = (*lock).len as _;
+
+    let host_cmd = match cmd {
+        FcntlCommand_F_GETLK => libc::F_GETLK,
+        FcntlCommand_F_SETLK => libc::F_SETLK,
+        _ => libc::F_SETLKW,
+    };
+    if libc::fcntl(fd, host_cmd, &mut host) < 0 {
+        return -last_errno();
+    }
+
+    if FcntlCommand_F_GETLK == cmd {
+        (*lock).type_ = match host.l_type as c_int {
+            libc::F_RDLCK => F_RDLCK,
+            libc::F_WRLCK => F_WRLCK,
+            _ => F_UNLCK,
+        } as i16;
+        (*lock).whence = host.l_whence as i16;
+        (*lock).start = host.l_start as i64;
+        (*lock).len = host.l_len as i64;
+        (*lock).pid = host.l_pid as i32;
+    }
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_fcntl(fd: c_int, cmd: FcntlCommand, arg: *mut c_void) -> c_int {
+    match cmd {
+        FcntlCommand_F_DUPFD | FcntlCommand_F_MVFD => {
+            let min_fd = *(arg as *const c_int);
+            let new_fd = libc::fcntl(fd, libc::F_DUPFD, min_fd);
+            if new_fd < 0 {
+                return -last_errno();
+            }
+            if FcntlCommand_F_MVFD == cmd && libc::close(fd) < 0 {
+                return -last_errno();
+            }
+            new_fd
+        }
+        FcntlCommand_F_GETFD => {
+            let flags = libc::fcntl(fd, libc::F_GETFD);
+            if flags < 0 {
+                return -last_errno();
+            }
+            if flags & libc::FD_CLOEXEC != 0 {
+                WASI_EXT_FDFLAG_CLOEXEC as c_int
+            } else {
+                0
+            }
+        }
+        FcntlCommand_F_SETFD => {
+            let flags = *(arg as *const wasi::Fdflags);
+            let host_flags = if flags & WASI_EXT_FDFLAG_CLOEXEC != 0 {
+                libc::FD_CLOEXEC
+            } else {
+                0
+            };
+            -check(libc::fcntl(fd, libc::F_SETFD, host_flags))
+        }
+        FcntlCommand_F_GETFL => {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            if flags < 0 {
+                return -last_errno();
+            }
+            host_fdflags_to_wasi(flags) as c_int
+        }
+        FcntlCommand_F_SETFL => {
+            // only append and nonblock can be changed on an open host fd
+            let flags = *(arg as *const wasi::Fdflags);
+            let mut host_flags = 0;
+            if flags & wasi::FDFLAGS_APPEND != 0 {
+                host_flags |= libc::O_APPEND;
+            }
+            if flags & wasi::FDFLAGS_NONBLOCK != 0 {
+                host_flags |= libc::O_NONBLOCK;
+            }
+            -check(libc::fcntl(fd, libc::F_SETFL, host_flags))
+        }
+        FcntlCommand_F_GETLK | FcntlCommand_F_SETLK | FcntlCommand_F_SETLKW => {
+            fcntl_lock(fd, cmd, arg as *mut Flock)
+        }
+        _ => -errno_raw(wasi::ERRNO_INVAL),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_pipe(fds: *mut c_int, flags: wasi::Fdflags) -> c_int {
+    let mut host_fds: [c_int; 2] = [-1; 2];
+    if libc::pipe(host_fds.as_mut_ptr()) < 0 {
+        return -last_errno();
+    }
+    for fd in host_fds {
+        let mut result = 0;
+        if flags & WASI_EXT_FDFLAG_CLOEXEC != 0 {
+            result = libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+        if result == 0 && flags & wasi::FDFLAGS_NONBLOCK != 0 {
+            result = libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK);
+        }
+        if result < 0 {
+            let err = last_errno();
+            libc::close(host_fds[0]);
+            libc::close(host_fds[1]);
+            return -err;
+        }
+    }
+    *fds = host_fds[0];
+    *fds.add(1) = host_fds[1];
+    0
+}
+
+// Fds that are not open are skipped, like close_range(2) does
+#[no_mangle]
+pub extern "C" fn wasi_ext_close_range(first: c_uint, last: c_uint, flags: c_uint) -> c_int {
+    for fd in first..=last.min(_MAX_FD_NUM - 1) {
+        let result = unsafe {
+            if flags & CLOSE_RANGE_CLOEXEC != 0 {
+                libc::fcntl(fd as c_int, libc::F_SETFD, libc::FD_CLOEXEC)
+            } else {
+                libc::close(fd as c_int)
+            }
+        };
+        if result < 0 && io::Error::last_os_error().raw_os_error() != Some(libc::EBADF) {
+            return last_errno();
+        }
+    }
+    0
+}
+
+#[cfg(target_os = "linux")]
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_memfd_create(name: *const c_char, flags: c_uint) -> c_int {
+    let host_flags = if flags & crate::wasi_ext_lib_generated::MFD_CLOEXEC != 0 {
+        libc::MFD_CLOEXEC
+    } else {
+        0
+    };
+    check_value(libc::memfd_create(name, host_flags))
+}
+
+#[cfg(not(target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn wasi_ext_memfd_create(_name: *const c_char, _flags: c_uint) -> c_int {
+    -ENOSYS
+}
+
+#[cfg(target_os = "linux")]
+#[no_mangle]
+pub extern "C" fn wasi_ext_copy_file_range(src_fd: c_int, dst_fd: c_int, len: usize) -> c_int {
+    let result =
+        unsafe { libc::copy_file_range(src_fd, ptr::null_mut(), dst_fd, ptr::null_mut(), len, 0) };
+    if result < 0 {
+        -last_errno()
+    } else {
+        result as c_int
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+#[no_mangle]
+pub extern "C" fn wasi_ext_copy_file_range(_src_fd: c_int, _dst_fd: c_int, _len: usize) -> c_int {
+    -ENOSYS
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_ext_mknod(_path: *const c_char, _dev: c_int) -> c_int {
+    ENOSYS
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_ext_umask(mask: c_int) -> c_int {
+    unsafe { libc::umask(mask as libc::mode_t) as c_int }
+}
+
+// The type of the resource argument differs between hosts
+macro_rules! host_resource {
+    ($resource:expr) => {
+        match $resource as u32 {
+            RLIMIT_CPU => libc::RLIMIT_CPU,
+            RLIMIT_NOFILE => libc::RLIMIT_NOFILE,
+            RLIMIT_AS => libc::RLIMIT_AS,
+            _ => return errno_raw(wasi::ERRNO_INVAL),
+        }
+    };
+}
+
+fn host_rlim(value: u64) -> libc::rlim_t {
+    if value == RLIM_INFINITY {
+        libc::RLIM_INFINITY
+    } else {
+        value
+    }
+}
+
+fn wasi_rlim(value: libc::rlim_t) -> u64 {
+    if value == libc::RLIM_INFINITY {
+        RLIM_INFINITY
+    } else {
+        value
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_getrlimit(resource: c_int, limit: *mut Rlimit) -> c_int {
+    let resource = host_resource!(resource);
+    let mut host: libc::rlimit = mem::zeroed();
+    if libc::getrlimit(resource, &mut host) < 0 {
+        return last_errno();
+    }
+    (*limit).cur = wasi_rlim(host.rlim_cur);
+    (*limit).max = wasi_rlim(host.rlim_max);
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_setrlimit(resource: c_int, limit: *const Rlimit) -> c_int {
+    let resource = host_resource!(resource);
+    let host = libc::rlimit {
+        rlim_cur: host_rlim((*limit).cur),
+        rlim_max: host_rlim((*limit).max),
+    };
+    check(libc::setrlimit(resource, &host))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_symlink(target: *const c_char, linkpath: *const c_char) -> c_int {
+    check(libc::symlink(target, linkpath))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_link(existing: *const c_char, new: *const c_char) -> c_int {
+    check(libc::link(existing, new))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_readlink(
+    path: *const c_char,
+    buf: *mut c_char,
+    buf_len: usize,
+) -> c_int {
+    match fs::read_link(os_str(path)) {
+        Ok(target) => write_buf(target.as_os_str().as_bytes(), buf, buf_len),
+        Err(e) => host_errno(&e),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_realpath(
+    path: *const c_char,
+    buf: *mut c_char,
+    buf_len: usize,
+) -> c_int {
+    match fs::canonicalize(os_str(path)) {
+        Ok(real) => write_buf(real.as_os_str().as_bytes(), buf, buf_len),
+        Err(e) => host_errno(&e),
+    }
+}
+
+// AccessMode uses the same values as the host F_OK, R_OK, W_OK and X_OK
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_access(path: *const c_char, mode: c_int) -> c_int {
+    check(libc::access(path, mode))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_mkdir(path: *const c_char, mode: c_int) -> c_int {
+    check(libc::mkdir(path, mode as libc::mode_t))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_rmdir(path: *const c_char) -> c_int {
+    check(libc::rmdir(path))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_rename(from: *const c_char, to: *const c_char) -> c_int {
+    check(libc::rename(from, to))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_chmod(path: *const c_char, mode: c_int) -> c_int {
+    check(libc::chmod(path, mode as libc::mode_t))
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_ext_fchmod(fd: c_int, mode: c_int) -> c_int {
+    check(unsafe { libc::fchmod(fd, mode as libc::mode_t) })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_chown(path: *const c_char, uid: c_int, gid: c_int) -> c_int {
+    check(libc::chown(path, uid as libc::uid_t, gid as libc::gid_t))
+}
+
+#[no_mangle]
+pub extern "C" fn wasi_ext_fchown(fd: c_int, uid: c_int, gid: c_int) -> c_int {
+    check(unsafe { libc::fchown(fd, uid as libc::uid_t, gid as libc::gid_t) })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_truncate(path: *const c_char, len: u64) -> c_int {
+    check(libc::truncate(path, len as libc::off_t))
+}
+
+// LOCK_* values are the same as on the host
+#[no_mangle]
+pub extern "C" fn wasi_ext_flock(fd: c_int, op: c_int) -> c_int {
+    check(unsafe { libc::flock(fd, op) })
+}
+
+fn wasi_filetype(mode: libc::mode_t) -> wasi::Filetype {
+    match mode & libc::S_IFMT {
+        libc::S_IFREG => wasi::FILETYPE_REGULAR_FILE,
+        libc::S_IFDIR => wasi::FILETYPE_DIRECTORY,
+        libc::S_IFLNK => wasi::FILETYPE_SYMBOLIC_LINK,
+        libc::S_IFCHR => wasi::FILETYPE_CHARACTER_DEVICE,
+        libc::S_IFBLK => wasi::FILETYPE_BLOCK_DEVICE,
+        libc::S_IFSOCK => wasi::FILETYPE_SOCKET_STREAM,
+        _ => wasi::FILETYPE_UNKNOWN,
+    }
+}
+
+fn timestamp(sec: libc::time_t, nsec: i64) -> u64 {
+    (sec as u64)
+        .wrapping_mul(1_000_000_000)
+        .wrapping_add(nsec as u64)
+}
+
+// Host device numbers are re-encoded the way the kernel reports them
+fn wasi_dev(dev: libc::dev_t) -> u64 {
+    let major = libc::major(dev) as u32;
+    let minor = libc::minor(dev) as u32;
+    crate::Dev::new(major, minor).raw() as u32 as u64
+}
+
+// Field types differ between hosts, the casts are not redundant everywhere
+#[allow(clippy::unnecessary_cast)]
+unsafe fn fill_stat(host: &libc::stat, stat: *mut Stat) {
+    *stat = Stat {
+        dev: wasi_dev(host.st_dev),
+        ino: host.st_ino as u64,
+        rdev: wasi_dev(host.st_rdev),
+        nlink: host.st_nlink as u64,
+        size: host.st_size as u64,
+        atim: timestamp(host.st_atime, host.st_atime_nsec as i64),
+        mtim: timestamp(host.st_mtime, host.st_mtime_nsec as i64),
+        ctim: timestamp(host.st_ctime, host.st_ctime_nsec as i64),
+        mode: host.st_mode as u32,
+        filetype: wasi_filetype(host.st_mode).raw(),
+    };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_stat(
+    path: *const c_char,
+    follow_symlinks: c_int,
+    stat: *mut Stat,
+) -> c_int {
+    let mut host: libc::stat = mem::zeroed();
+    let result = if follow_symlinks != 0 {
+        libc::stat(path, &mut host)
+    } else {
+        libc::lstat(path, &mut host)
+    };
+    if result < 0 {
+        return last_errno();
+    }
+    fill_stat(&host, stat);
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_fstat(fd: c_int, stat: *mut Stat) -> c_int {
+    let mut host: libc::stat = mem::zeroed();
+    if libc::fstat(fd, &mut host) < 0 {
+        return last_errno();
+    }
+    fill_stat(&host, stat);
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_statvfs(path: *const c_char, stat: *mut StatVfs) -> c_int {
+    let mut host: libc::statvfs = mem::zeroed();
+    if libc::statvfs(path, &mut host) < 0 {
+        return last_errno();
+    }
+    // block counts of statvfs are in fragment size units
+    *stat = StatVfs {
+        bsize: host.f_frsize as u64,
+        blocks: host.f_blocks as u64,
+        bfree: host.f_bfree as u64,
+        bavail: host.f_bavail as u64,
+        files: host.f_files as u64,
+        ffree: host.f_ffree as u64,
+        namemax: host.f_namemax as u64,
+    };
+    0
+}
+
+#[cfg(feature = "sockets")]
+#[no_mangle]
+pub extern "C" fn wasi_ext_socket(domain: c_int, type_: c_int, protocol: c_int) -> c_int {
+    use crate::wasi_ext_lib_generated::{
+        WASI_EXT_AF_INET, WASI_EXT_AF_INET6, WASI_EXT_SOCK_DGRAM, WASI_EXT_SOCK_STREAM,
+    };
+    let host_domain = match domain as u32 {
+        WASI_EXT_AF_INET => libc::AF_INET,
+        WASI_EXT_AF_INET6 => libc::AF_INET6,
+        _ => return -errno_raw(wasi::ERRNO_INVAL),
+    };
+    let host_type = match type_ as u32 {
+        WASI_EXT_SOCK_STREAM => libc::SOCK_STREAM,
+        WASI_EXT_SOCK_DGRAM => libc::SOCK_DGRAM,
+        _ => return -errno_raw(wasi::ERRNO_INVAL),
+    };
+    check_value(unsafe { libc::socket(host_domain, host_type, protocol) })
+}
+
+// Addresses come as the text form of std::net::SocketAddr
+#[cfg(feature = "sockets")]
+unsafe fn with_sockaddr<F>(addr: *const c_char, f: F) -> c_int
+where
+    F: FnOnce(*const libc::sockaddr, libc::socklen_t) -> c_int,
+{
+    use std::net::SocketAddr;
+
+    let addr = match CStr::from_ptr(addr).to_str().map(str::parse::<SocketAddr>) {
+        Ok(Ok(addr)) => addr,
+        _ => return errno_raw(wasi::ERRNO_INVAL),
+    };
+    match addr {
+        SocketAddr::V4(addr) => {
+            let mut host: libc::sockaddr_in = mem::zeroed();
+            host.sin_family = libc::AF_INET as libc::sa_family_t;
+            host.sin_port = addr.port().to_be();
+            host.sin_addr.s_addr = u32::from_ne_bytes(addr.ip().octets());
+            f(
+                &host as *const libc::sockaddr_in as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+            )
+        }
+        SocketAddr::V6(addr) => {
+            let mut host: libc::sockaddr_in6 = mem::zeroed();
+            host.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            host.sin6_port = addr.port().to_be();
+            host.sin6_flowinfo = addr.flowinfo();
+            host.sin6_addr.s6_addr = addr.ip().octets();
+            host.sin6_scope_id = addr.scope_id();
+            f(
+                &host as *const libc::sockaddr_in6 as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t,
+            )
+        }
+    }
+}
+
+#[cfg(feature = "sockets")]
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_bind(fd: c_int, addr: *const c_char) -> c_int {
+    with_sockaddr(addr, |addr, len| check(libc::bind(fd, addr, len)))
+}
+
+#[cfg(feature = "sockets")]
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_connect(fd: c_int, addr: *const c_char) -> c_int {
+    with_sockaddr(addr, |addr, len| check(libc::connect(fd, addr, len)))
+}
+
+#[cfg(feature = "sockets")]
+#[no_mangle]
+pub extern "C" fn wasi_ext_listen(fd: c_int, backlog: c_int) -> c_int {
+    check(unsafe { libc::listen(fd, backlog) })
+}
+
+// Port of third_party/termios/termios.c, terminal calls go through the mock
+// wasi_ext_ioctl above
+
+fn errno_inval() -> c_int {
+    errno_raw(wasi::ERRNO_INVAL)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_cfgetospeed(termios_p: *const termios::termios) -> speed_t {
+    (*termios_p).c_cflag & termios::CBAUD
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_cfgetispeed(termios_p: *const termios::termios) -> speed_t {
+    wasi_ext_cfgetospeed(termios_p)
+}
+
+const SPEEDS: [speed_t; 31] = [
+    termios::B0,
+    termios::B50,
+    termios::B75,
+    termios::B110,
+    termios::B134,
+    termios::B150,
+    termios::B200,
+    termios::B300,
+    termios::B600,
+    termios::B1200,
+    termios::B1800,
+    termios::B2400,
+    termios::B4800,
+    termios::B9600,
+    termios::B19200,
+    termios::B38400,
+    termios::B57600,
+    termios::B115200,
+    termios::B230400,
+    termios::B460800,
+    termios::B500000,
+    termios::B576000,
+    termios::B921600,
+    termios::B1000000,
+    termios::B1152000,
+    termios::B1500000,
+    termios::B2000000,
+    termios::B2500000,
+    termios::B3000000,
+    termios::B3500000,
+    termios::B4000000,
+];
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_cfsetospeed(
+    termios_p: *mut termios::termios,
+    speed: speed_t,
+) -> c_int {
+    if !SPEEDS.contains(&speed) {
+        return -errno_inval();
+    }
+    (*termios_p).c_cflag &= !termios::CBAUD;
+    (*termios_p).c_cflag |= speed;
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_cfsetispeed(
+    termios_p: *mut termios::termios,
+    speed: speed_t,
+) -> c_int {
+    if speed != 0 {
+        wasi_ext_cfsetospeed(termios_p, speed)
+    } else {
+        0
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_cfsetspeed(
+    termios_p: *mut termios::termios,
+    speed: speed_t,
+) -> c_int {
+    wasi_ext_cfsetospeed(termios_p, speed)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_cfmakeraw(termios_p: *mut termios::termios) {
+    let t = &mut *termios_p;
+    t.c_iflag &= !(termios::IGNBRK
+        | termios::BRKINT
+        | termios::PARMRK
+        | termios::ISTRIP
+        | termios::INLCR
+        | termios::IGNCR
+        | termios::ICRNL
+        | termios::IXON);
+    t.c_oflag &= !termios::OPOST;
+    t.c_lflag &=
+        !(termios::ECHO | termios::ECHONL | termios::ICANON | termios::ISIG | termios::IEXTEN);
+    t.c_cflag &= !(termios::CSIZE | termios::PARENB);
+    t.c_cflag |= termios::CS8;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_tcgetattr(fd: c_int, tio: *mut termios::termios) -> c_int {
+    -wasi_ext_ioctl(fd, termios::TCGETS, tio as *mut c_void)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_tcsetattr(
+    fd: c_int,
+    act: c_int,
+    tio: *const termios::termios,
+) -> c_int {
+    match act as u32 {
+        termios::TCSANOW => -wasi_ext_ioctl(fd, termios::TCSETS, tio as *mut c_void),
+        termios::TCSADRAIN | termios::TCSAFLUSH => -errno_raw(wasi::ERRNO_NOTSUP),
+        _ => -errno_inval(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_tcgetwinsize(fd: c_int, winsize_p: *mut winsize) -> c_int {
+    -wasi_ext_ioctl(fd, termios::TIOCGWINSZ, winsize_p as *mut c_void)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_tcsetwinsize(fd: c_int, winsize_p: *const winsize) -> c_int {
+    -wasi_ext_ioctl(fd, termios::TIOCSWINSZ, winsize_p as *mut c_void)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_tcsendbreak(fd: c_int, _duration: c_int) -> c_int {
+    let mut arg: c_int = 0;
+    -wasi_ext_ioctl(fd, termios::TCSBRK, &mut arg as *mut c_int as *mut c_void)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_tcdrain(fd: c_int) -> c_int {
+    let mut arg: c_int = 1;
+    -wasi_ext_ioctl(fd, termios::TCSBRK, &mut arg as *mut c_int as *mut c_void)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_tcflush(fd: c_int, mut queue: c_int) -> c_int {
+    -wasi_ext_ioctl(fd, termios::TCFLSH, &mut queue as *mut c_int as *mut c_void)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_tcflow(fd: c_int, mut action: c_int) -> c_int {
+    -wasi_ext_ioctl(
+        fd,
+        termios::TCXONC,
+        &mut action as *mut c_int as *mut c_void,
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_tcgetsid(fd: c_int) -> pid_t {
+    let mut sid: pid_t = 0;
+    let err = wasi_ext_ioctl(fd, termios::TIOCGSID, &mut sid as *mut pid_t as *mut c_void);
+    if err < 0 {
+        return err;
+    }
+    sid
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_tcgetpgrp(fd: c_int) -> pid_t {
+    let mut pgrp: pid_t = 0;
+    let err = wasi_ext_ioctl(
+        fd,
+        termios::TIOCGPGRP,
+        &mut pgrp as *mut pid_t as *mut c_void,
+    );
+    if err < 0 {
+        return err;
+    }
+    pgrp
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasi_ext_tcsetpgrp(fd: c_int, mut pgrp: pid_t) -> c_int {
+    -wasi_ext_ioctl(
+        fd,
+        termios::TIOCSPGRP,
+        &mut pgrp as *mut pid_t as *mut c_void,
+    )
+}
+
+// WASI preview1 functions called through the wasi crate. On native targets it
+// passes pointers as i32, which cannot hold a host address, so the crate root
+// imports this module as wasi instead. Everything else is the wasi crate's
+pub(crate) mod wasi {
+    pub use ::wasi::*;
+
+    use std::ffi::{c_int, c_void, CStr, CString};
+    use std::fs;
+    use std::io::{self, Read};
+    use std::mem;
+    use std::ptr;
+    use std::slice;
+
+    use super::{host_fdflags_to_wasi, wasi_dev, wasi_errno, wasi_filetype};
+
+    fn last_error() -> Errno {
+        wasi_errno(&io::Error::last_os_error())
+    }
+
+    fn check(result: c_int) -> Result<(), Errno> {
+        if result < 0 {
+            Err(last_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_size(result: isize) -> Result<Size, Errno> {
+        if result < 0 {
+            Err(last_error())
+        } else {
+            Ok(result as Size)
+        }
+    }
+
+    // Same as the kernel, paths are resolved relative to the fd and cannot be
+    // absolute
+    fn path_arg(path: &str) -> Result<CString, Errno> {
+        if path.starts_with('/') {
+            return Err(ERRNO_NOTCAPABLE);
+        }
+        CString::new(path).map_err(|_| ERRNO_INVAL)
+    }
+
+    fn times(atim: Timestamp, mtim: Timestamp, fst_flags: Fstflags) -> [libc::timespec; 2] {
+        let time = |ns: Timestamp, set: Fstflags, now: Fstflags| {
+            let mut spec: libc::timespec = unsafe { mem::zeroed() };
+            if fst_flags & now != 0 {
+                spec.tv_nsec = libc::UTIME_NOW;
+            } else if fst_flags & set != 0 {
+                spec.tv_sec = (ns / 1_000_000_000) as libc::time_t;
+                spec.tv_nsec = (ns % 1_000_000_000) as _;
+            } else {
+                spec.tv_nsec = libc::UTIME_OMIT;
+            }
+            spec
+        };
+        [
+            time(atim, FSTFLAGS_ATIM, FSTFLAGS_ATIM_NOW),
+            time(mtim, FSTFLAGS_MTIM, FSTFLAGS_MTIM_NOW),
+        ]
+    }
+
+    pub unsafe fn fd_close(fd: Fd) -> Result<(), Errno> {
+        check(libc::close(fd as c_int))
+    }
+
+    pub unsafe fn fd_sync(fd: Fd) -> Result<(), Errno> {
+        check(libc::fsync(fd as c_int))
+    }
+
+    pub unsafe fn fd_datasync(fd: Fd) -> Result<(), Errno> {
+        fd_sync(fd)
+    }
+
+    // The kernel treats fd_renumber like dup2, see F_DUPFD in c_lib
+    pub unsafe fn fd_renumber(fd: Fd, to: Fd) -> Result<(), Errno> {
+        check(libc::dup2(fd as c_int, to as c_int))
+    }
+
+    pub unsafe fn fd_filestat_set_size(fd: Fd, size: Filesize) -> Result<(), Errno> {
+        check(libc::ftruncate(fd as c_int, size as libc::off_t))
+    }
+
+    pub unsafe fn fd_filestat_set_times(
+        fd: Fd,
+        atim: Timestamp,
+        mtim: Timestamp,
+        fst_flags: Fstflags,
+    ) -> Result<(), Errno> {
+        check(libc::futimens(
+            fd as c_int,
+            times(atim, mtim, fst_flags).as_ptr(),
+        ))
+    }
+
+    pub unsafe fn fd_read(fd: Fd, iovs: IovecArray<'_>) -> Result<Size, Errno> {
+        let iovs: Vec<libc::iovec> = iovs
+            .iter()
+            .map(|iov| libc::iovec {
+                iov_base: iov.buf as *mut c_void,
+                iov_len: iov.buf_len,
+            })
+            .collect();
+        check_size(libc::readv(fd as c_int, iovs.as_ptr(), iovs.len() as c_int))
+    }
+
+    pub unsafe fn fd_write(fd: Fd, iovs: CiovecArray<'_>) -> Result<Size, Errno> {
+        let iovs: Vec<libc::iovec> = iovs
+            .iter()
+            .map(|iov| libc::iovec {
+                iov_base: iov.buf as *mut c_void,
+                iov_len: iov.buf_len,
+            })
+            .collect();
+        check_size(libc::writev(
+            fd as c_int,
+            iovs.as_ptr(),
+            iovs.len() as c_int,
+        ))
+    }
+
+    // __wasi_whence_t values are the same as SEEK_SET, SEEK_CUR and SEEK_END
+    pub unsafe fn fd_seek(fd: Fd, offset: Filedelta, whence: Whence) -> Result<Filesize, Errno> {
+        let result = libc::lseek(fd as c_int, offset as libc::off_t, whence.raw() as c_int);
+        if result < 0 {
+            return Err(last_error());
+        }
+        Ok(result as Filesize)
+    }
+
+    // Rights are not tracked, every fd gets all of them
+    pub unsafe fn fd_fdstat_get(fd: Fd) -> Result<Fdstat, Errno> {
+        let flags = libc::fcntl(fd as c_int, libc::F_GETFL);
+        if flags < 0 {
+            return Err(last_error());
+        }
+        let mut host: libc::stat = mem::zeroed();
+        check(libc::fstat(fd as c_int, &mut host))?;
+        Ok(Fdstat {
+            fs_filetype: wasi_filetype(host.st_mode),
+            fs_flags: host_fdflags_to_wasi(flags),
+            fs_rights_base: !0,
+            fs_rights_inheriting: !0,
+        })
+    }
+
+    // Field types differ between hosts, the casts are not redundant everywhere
+    #[allow(clippy::unnecessary_cast)]
+    pub unsafe fn fd_filestat_get(fd: Fd) -> Result<Filestat, Errno> {
+        let mut host: libc::stat = mem::zeroed();
+        check(libc::fstat(fd as c_int, &mut host))?;
+        Ok(Filestat {
+            dev: wasi_dev(host.st_dev),
+            ino: host.st_ino as u64,
+            filetype: wasi_filetype(host.st_mode),
+            nlink: host.st_nlink as u64,
+            size: host.st_size as u64,
+            atim: super::timestamp(host.st_atime, host.st_atime_nsec as i64),
+            mtim: super::timestamp(host.st_mtime, host.st_mtime_nsec as i64),
+            ctim: super::timestamp(host.st_ctime, host.st_ctime_nsec as i64),
+        })
+    }
+
+    fn dirent_type(d_type: u8) -> Filetype {
+        match d_type {
+            libc::DT_REG => FILETYPE_REGULAR_FILE,
+            libc::DT_DIR => FILETYPE_DIRECTORY,
+            libc::DT_LNK => FILETYPE_SYMBOLIC_LINK,
+            libc::DT_CHR => FILETYPE_CHARACTER_DEVICE,
+            libc::DT_BLK => FILETYPE_BLOCK_DEVICE,
+            libc::DT_SOCK => FILETYPE_SOCKET_STREAM,
+            _ => FILETYPE_UNKNOWN,
+        }
+    }
+
+    // Cookies are entry indexes, the directory is read from the start on
+    // every call. Like on the kernel the last entry is cut off if the buffer
+    // is full
+    #[allow(clippy::unnecessary_cast)]
+    pub unsafe fn fd_readdir(
+        fd: Fd,
+        buf: *mut u8,
+        buf_len: Size,
+        cookie: Dircookie,
+    ) -> Result<Size, Errno> {
+        let dup = libc::dup(fd as c_int);
+        if dup < 0 {
+            return Err(last_error());
+        }
+        let dir = libc::fdopendir(dup);
+        if dir.is_null() {
+            let err = last_error();
+            libc::close(dup);
+            return Err(err);
+        }
+        libc::rewinddir(dir);
+
+        let mut output = Vec::new();
+        let mut index: Dircookie = 0;
+        while output.len() < buf_len {
+            let entry = libc::readdir(dir);
+            if entry.is_null() {
+                break;
+            }
+            index += 1;
+            if index <= cookie {
+                continue;
+            }
+            let name = CStr::from_ptr((*entry).d_name.as_ptr()).to_bytes();
+            // __wasi_dirent_t is followed by 3 bytes of padding
+            output.extend_from_slice(&index.to_ne_bytes());
+            output.extend_from_slice(&((*entry).d_ino as u64).to_ne_bytes());
+            output.extend_from_slice(&(name.len() as u32).to_ne_bytes());
+            output.extend_from_slice(&[dirent_type((*entry).d_type).raw(), 0, 0, 0]);
+            output.extend_from_slice(name);
+        }
+        libc::closedir(dir);
+
+        let len = output.len().min(buf_len);
+        ptr::copy_nonoverlapping(output.as_ptr(), buf, len);
+        Ok(len)
+    }
+
+    pub unsafe fn path_open(
+        fd: Fd,
+        dirflags: Lookupflags,
+        path: &str,
+        oflags: Oflags,
+        fs_rights_base: Rights,
+        _fs_rights_inheriting: Rights,
+        fdflags: Fdflags,
+    ) -> Result<Fd, Errno> {
+        let path = path_arg(path)?;
+        let read = fs_rights_base & RIGHTS_FD_READ != 0;
+        let write = fs_rights_base & RIGHTS_FD_WRITE != 0;
+        let mut flags = match (read, write) {
+            (_, false) => libc::O_RDONLY,
+            (false, true) => libc::O_WRONLY,
+            (true, true) => libc::O_RDWR,
+        };
+        if dirflags & LOOKUPFLAGS_SYMLINK_FOLLOW == 0 {
+            flags |= libc::O_NOFOLLOW;
+        }
+        for (oflag, host_flag) in [
+            (OFLAGS_CREAT, libc::O_CREAT),
+            (OFLAGS_EXCL, libc::O_EXCL),
+            (OFLAGS_TRUNC, libc::O_TRUNC),
+            (OFLAGS_DIRECTORY, libc::O_DIRECTORY),
+        ] {
+            if oflags & oflag != 0 {
+                flags |= host_flag;
+            }
+        }
+        for (fdflag, host_flag) in [
+            (FDFLAGS_APPEND, libc::O_APPEND),
+            (FDFLAGS_NONBLOCK, libc::O_NONBLOCK),
+            (FDFLAGS_DSYNC, libc::O_DSYNC),
+            (FDFLAGS_SYNC, libc::O_SYNC),
+        ] {
+            if fdflags & fdflag != 0 {
+                flags |= host_flag;
+            }
+        }
+        let opened = libc::openat(fd as c_int, path.as_ptr(), flags, 0o666 as libc::c_uint);
+        if opened < 0 {
+            return Err(last_error());
+        }
+        Ok(opened as Fd)
+    }
+
+    pub unsafe fn path_create_directory(fd: Fd, path: &str) -> Result<(), Errno> {
+        let path = path_arg(path)?;
+        check(libc::mkdirat(fd as c_int, path.as_ptr(), 0o777))
+    }
+
+    pub unsafe fn path_remove_directory(fd: Fd, path: &str) -> Result<(), Errno> {
+        let path = path_arg(path)?;
+        check(libc::unlinkat(
+            fd as c_int,
+            path.as_ptr(),
+            libc::AT_REMOVEDIR,
+        ))
+    }
+
+    pub unsafe fn path_unlink_file(fd: Fd, path: &str) -> Result<(), Errno> {
+        let path = path_arg(path)?;
+        check(libc::unlinkat(fd as c_int, path.as_ptr(), 0))
+    }
+
+    pub unsafe fn path_filestat_set_times(
+        fd: Fd,
+        flags: Lookupflags,
+        path: &str,
+        atim: Timestamp,
+        mtim: Timestamp,
+        fst_flags: Fstflags,
+    ) -> Result<(), Errno> {
+        let path = path_arg(path)?;
+        let host_flags = if flags & LOOKUPFLAGS_SYMLINK_FOLLOW != 0 {
+            0
+        } else {
+            libc::AT_SYMLINK_NOFOLLOW
+        };
+        check(libc::utimensat(
+            fd as c_int,
+            path.as_ptr(),
+            times(atim, mtim, fst_flags).as_ptr(),
+            host_flags,
+        ))
+    }
+
+    // Clock subscriptions are taken as relative timeouts, which is the only
+    // kind the crate subscribes to
+    pub unsafe fn poll_oneoff(
+        in_: *const Subscription,
+        out: *mut Event,
+        nsubscriptions: Size,
+    ) -> Result<Size, Errno> {
+        let subscriptions = slice::from_raw_parts(in_, nsubscriptions);
+        let mut fds = Vec::new();
+        let mut fd_subscriptions = Vec::new();
+        let mut timeout: Option<&Subscription> = None;
+        for subscription in subscriptions {
+            let (fd, events) = match subscription.u.tag {
+                tag if tag == EVENTTYPE_CLOCK.raw() => {
+                    match timeout {
+                        Some(t) if t.u.u.clock.timeout <= subscription.u.u.clock.timeout => {}
+                        _ => timeout = Some(subscription),
+                    }
+                    continue;
+                }
+                tag if tag == EVENTTYPE_FD_READ.raw() => {
+                    (subscription.u.u.fd_read.file_descriptor, libc::POLLIN)
+                }
+                tag if tag == EVENTTYPE_FD_WRITE.raw() => {
+                    (subscription.u.u.fd_write.file_descriptor, libc::POLLOUT)
+                }
+                _ => return Err(ERRNO_INVAL),
+            };
+            fds.push(libc::pollfd {
+                fd: fd as c_int,
+                events,
+                revents: 0,
+            });
+            fd_subscriptions.push(subscription);
+        }
+
+        // rounded up so that a sleep never ends early
+        let timeout_ms = timeout.map_or(-1, |t| {
+            let ms = t.u.u.clock.timeout.div_ceil(1_000_000);
+            c_int::try_from(ms).unwrap_or(c_int::MAX)
+        });
+        let ready = libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout_ms);
+        if ready < 0 {
+            return Err(last_error());
+        }
+
+        let events = slice::from_raw_parts_mut(out, nsubscriptions);
+        let mut n_events = 0;
+        for (pollfd, subscription) in fds.iter().zip(fd_subscriptions) {
+            if pollfd.revents == 0 {
+                continue;
+            }
+            let mut event: Event = mem::zeroed();
+            event.userdata = subscription.userdata;
+            event.error = if pollfd.revents & libc::POLLNVAL != 0 {
+                ERRNO_BADF
+            } else {
+                ERRNO_SUCCESS
+            };
+            event.type_ = if pollfd.events == libc::POLLIN {
+                EVENTTYPE_FD_READ
+            } else {
+                EVENTTYPE_FD_WRITE
+            };
+            if pollfd.revents & libc::POLLHUP != 0 {
+                event.fd_readwrite.flags = EVENTRWFLAGS_FD_READWRITE_HANGUP;
+            }
+            events[n_events] = event;
+            n_events += 1;
+        }
+        if let (0, Some(subscription)) = (ready, timeout) {
+            let mut event: Event = mem::zeroed();
+            event.userdata = subscription.userdata;
+            event.error = ERRNO_SUCCESS;
+            event.type_ = EVENTTYPE_CLOCK;
+            events[n_events] = event;
+            n_events += 1;
+        }
+        Ok(n_events)
+    }
+
+    pub unsafe fn random_get(buf: *mut u8, buf_len: Size) -> Result<(), Errno> {
+        let buf = slice::from_raw_parts_mut(buf, buf_len);
+        fs::File::open("/dev/urandom")
+            .and_then(|mut urandom| urandom.read_exact(buf))
+            .map_err(|e| wasi_errno(&e))
+    }
+
+    #[cfg(feature = "sockets")]
+    pub unsafe fn sock_accept(fd: Fd, flags: Fdflags) -> Result<Fd, Errno> {
+        let accepted = libc::accept(fd as c_int, ptr::null_mut(), ptr::null_mut());
+        if accepted < 0 {
+            return Err(last_error());
+        }
+        if flags & FDFLAGS_NONBLOCK != 0
+            && libc::fcntl(accepted, libc::F_SETFL, libc::O_NONBLOCK) < 0
+        {
+            let err = last_error();
+            libc::close(accepted);
+            return Err(err);
+        }
+        Ok(accepted as Fd)
+    }
+}
@@ -0,0 +1,1878 @@
+/*
+ * Copyright (c) 2022-2023 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+// Natively the tests need the mock feature, `cargo test --features mock`.
+// Tests in the host module call into the mock, the rest are pure logic
+
+use super::*;
+
+fn inval<T>() -> Result<T, WasiExtError> {
+    Err(WasiExtError::Errno(wasi::ERRNO_INVAL))
+}
+
+#[test]
+fn redirect_parse_operators() {
+    let cases = [
+        ("2>err", Redirect::Write(2, String::from("err"))),
+        (">out", Redirect::Write(1, String::from("out"))),
+        (">>log", Redirect::Append(1, String::from("log"))),
+        ("2>>log", Redirect::Append(2, String::from("log"))),
+        ("<in", Redirect::Read(0, String::from("in"))),
+        ("<>file", Redirect::ReadWrite(0, String::from("file"))),
+        (
+            "3<>>file",
+            Redirect::ReadWriteAppend(3, String::from("file")),
+        ),
+        (
+            "2>&1",
+            Redirect::Duplicate {
+                fd_src: 1,
+                fd_dst: 2,
+            },
+        ),
+        (
+            "<&4",
+            Redirect::Duplicate {
+                fd_src: 4,
+                fd_dst: 0,
+            },
+        ),
+        (">&-", Redirect::Close(1)),
+        ("<&-", Redirect::Close(0)),
+        ("2> spaced", Redirect::Write(2, String::from("spaced"))),
+    ];
+    for (token, expected) in cases {
+        let redirect = token.parse::<Redirect>().unwrap();
+        assert_eq!(redirect, expected, "{token}");
+        let displayed = redirect.to_string();
+        assert_eq!(displayed.parse::<Redirect>(), Ok(expected), "{displayed}");
+    }
+}
+
+#[test]
+fn redirect_display_pipes() {
+    let pipe_in = Redirect::PipeIn(5).to_string();
+    assert_eq!(pipe_in, "0<&5");
+    let pipe_out = Redirect::PipeOut(6).to_string();
+    assert_eq!(pipe_out, "1>&6");
+    assert!(matches!(
+        pipe_out.parse(),
+        Ok(Redirect::Duplicate {
+            fd_src: 6,
+            fd_dst: 1
+        })
+    ));
+}
+
+#[test]
+fn redirect_parse_rejects_invalid() {
+    for token in [
+        "",
+        "2",
+        ">&x",
+        "<&1x",
+        ">",
+        "2>",
+        "2>>",
+        "<> ",
+        "x>out",
+        "99999999999>out",
+    ] {
+        assert_eq!(token.parse::<Redirect>(), inval(), "{token:?}");
+    }
+}
+
+#[test]
+fn dev_roundtrip_full_range() {
+    let edges = [0, 1, Dev::MINOR_MAX - 1, Dev::MINOR_MAX];
+    for major in 0..=Dev::MAJOR_MAX {
+        for minor in edges {
+            let dev = Dev::try_new(major, minor).unwrap();
+            assert_eq!((dev.major(), dev.minor()), (major, minor));
+            assert_eq!(Dev::from_raw(dev.raw()), dev);
+            assert_eq!(mkdev(major as i32, minor as i32), dev.raw());
+        }
+    }
+    for minor in 0..=Dev::MINOR_MAX {
+        for major in [0, 1, Dev::MAJOR_MAX] {
+            let dev = Dev::new(major, minor);
+            assert_eq!((dev.major(), dev.minor()), (major, minor));
+        }
+    }
+}
+
+#[test]
+fn dev_minor_boundary() {
+    // The lowest major bit sits right above the 20 minor bits
+    assert_eq!(Dev::new(1, 0).raw(), 1 << 20);
+    assert_eq!(Dev::new(0, Dev::MINOR_MAX).raw(), (1 << 20) - 1);
+    assert_eq!(Dev::new(Dev::MAJOR_MAX, 0).raw(), 0xfff0_0000_u32 as i32);
+    assert_eq!(Dev::from_raw(1 << 20).major(), 1);
+    assert_eq!(Dev::from_raw(1 << 20).minor(), 0);
+}
+
+#[test]
+fn dev_rejects_out_of_range() {
+    assert_eq!(Dev::try_new(Dev::MAJOR_MAX + 1, 0), inval());
+    assert_eq!(Dev::try_new(0, Dev::MINOR_MAX + 1), inval());
+    assert_eq!(Dev::try_new(u32::MAX, u32::MAX), inval());
+    assert_eq!(
+        Dev::new(Dev::MAJOR_MAX + 1, Dev::MINOR_MAX + 1),
+        Dev::new(0, 0)
+    );
+}
+
+#[test]
+fn utimes_rejects_times_before_epoch() {
+    let before_epoch = Timestamp::At(UNIX_EPOCH - Duration::from_secs(1));
+    assert_eq!(utimes("unused", None, Some(before_epoch)), inval());
+    assert_eq!(futimens(0, Some(before_epoch), None), inval());
+}
+
+#[test]
+fn stdio_to_redirect() {
+    let file = || String::from("file");
+    let cases = [
+        (Stdio::Inherit, 0, None),
+        (Stdio::Inherit, 2, None),
+        (
+            Stdio::Null,
+            0,
+            Some(Redirect::Read(0, String::from(DEV_NULL))),
+        ),
+        (
+            Stdio::Null,
+            1,
+            Some(Redirect::Write(1, String::from(DEV_NULL))),
+        ),
+        (
+            Stdio::Null,
+            2,
+            Some(Redirect::Write(2, String::from(DEV_NULL))),
+        ),
+        (Stdio::Pipe(7), 0, Some(Redirect::PipeIn(7))),
+        (Stdio::Pipe(7), 1, Some(Redirect::PipeOut(7))),
+        (
+            Stdio::Pipe(7),
+            2,
+            Some(Redirect::Duplicate {
+                fd_src: 7,
+                fd_dst: 2,
+            }),
+        ),
+        (
+            Stdio::Fd(5),
+            1,
+            Some(Redirect::Duplicate {
+                fd_src: 5,
+                fd_dst: 1,
+            }),
+        ),
+        (Stdio::File(file()), 0, Some(Redirect::Read(0, file()))),
+        (Stdio::File(file()), 1, Some(Redirect::Write(1, file()))),
+        (Stdio::File(file()), 2, Some(Redirect::Write(2, file()))),
+        (Stdio::Close, 0, Some(Redirect::Close(0))),
+        (Stdio::Close, 1, Some(Redirect::Close(1))),
+        (Stdio::Close, 2, Some(Redirect::Close(2))),
+    ];
+    for (stdio, fd, expected) in cases {
+        assert_eq!(stdio.to_redirect(fd), expected, "{stdio:?} on {fd}");
+    }
+}
+
+#[test]
+fn command_stdio_replaces_redirects() {
+    let mut command = Command::new("cat");
+    command
+        .redirect(Redirect::Write(1, String::from("first")))
+        .stdin(Stdio::Null)
+        .stdout(Stdio::Pipe(4))
+        .stderr(Stdio::Inherit);
+    assert_eq!(
+        command.get_redirects(),
+        [
+            Redirect::Read(0, String::from(DEV_NULL)),
+            Redirect::PipeOut(4)
+        ]
+    );
+}
+
+#[test]
+fn signal_from_name_spellings() {
+    for name in ["TERM", "SIGTERM", "sigterm", "SigTerm", "term", "15"] {
+        assert_eq!(signal_from_name(name), Some(wasi::SIGNAL_TERM), "{name}");
+    }
+    assert_eq!(signal_name(wasi::SIGNAL_TERM), "TERM");
+}
+
+#[test]
+fn signal_name_roundtrip() {
+    let mut count = 0;
+    for signal in signals() {
+        let name = signal_name(signal);
+        assert_eq!(signal_from_name(name), Some(signal), "{name}");
+        assert_eq!(signal_from_name(&format!("SIG{name}")), Some(signal));
+        let number = signal.raw().to_string();
+        assert_eq!(signal_from_name(&number), Some(signal));
+        count += 1;
+    }
+    assert_eq!(count, WASI_SIGNAL_MAX);
+}
+
+#[test]
+fn signal_from_name_rejects_unknown() {
+    for name in [
+        "SIGFOO",
+        "FOO",
+        "99",
+        "31",
+        "-1",
+        "",
+        "SIG",
+        "SIGSIGTERM",
+        " TERM",
+    ] {
+        assert_eq!(signal_from_name(name), None, "{name:?}");
+    }
+}
+
+#[test]
+fn wait_status_decoding() {
+    let stop = wasi::SIGNAL_STOP.raw() as ExitCode;
+    let cases = [
+        (0, WaitStatus::Exited(0)),
+        (3, WaitStatus::Exited(3)),
+        (EXIT_SIGNAL_BASE, WaitStatus::Exited(EXIT_SIGNAL_BASE)),
+        (
+            EXIT_SIGNAL_BASE + wasi::SIGNAL_TERM.raw() as ExitCode,
+            WaitStatus::Signaled(wasi::SIGNAL_TERM),
+        ),
+        (
+            stop << 8 | wasi_ext_lib_generated::WSTOPPED_MARK as ExitCode,
+            WaitStatus::Stopped(wasi::SIGNAL_STOP),
+        ),
+        (
+            wasi_ext_lib_generated::WCONTINUED_STATUS as ExitCode,
+            WaitStatus::Continued,
+        ),
+        // the stop mark alone is an exit code
+        (
+            wasi_ext_lib_generated::WSTOPPED_MARK as ExitCode,
+            WaitStatus::Exited(wasi_ext_lib_generated::WSTOPPED_MARK as ExitCode),
+        ),
+    ];
+    for (raw, expected) in cases {
+        assert_eq!(WaitStatus::from_raw(raw), expected, "{raw:#x}");
+    }
+}
+
+// CString::new fails before anything reaches the kernel
+#[test]
+fn embedded_nul_is_einval() {
+    assert_eq!(set_env("KEY\0", Some("val")), inval());
+    assert_eq!(set_env("KEY", Some("v\0al")), inval());
+
+    assert_eq!(mount("/dev\0", "/mnt", "ext2", 0, ""), inval());
+    assert_eq!(mount("/dev", "/m\0nt", "ext2", 0, ""), inval());
+    assert_eq!(mount("/dev", "/mnt", "e\0xt2", 0, ""), inval());
+    assert_eq!(mount("/dev", "/mnt", "ext2", 0, "ro\0"), inval());
+    let options = MountOptions::new();
+    assert!(matches!(
+        mount_scoped("/dev", "/m\0nt", "ext2", MountFlags::empty(), &options),
+        Err(WasiExtError::Errno(wasi::ERRNO_INVAL))
+    ));
+    assert_eq!(umount("/m\0nt"), inval());
+    assert_eq!(mknod("/dev/n\0ull", Dev::new(1, 3)), inval());
+
+    assert_eq!(Command::new("/bin/s\0h").spawn(), inval());
+    assert_eq!(Command::new("/bin/sh").arg("-\0c").spawn(), inval());
+    assert_eq!(Command::new("/bin/sh").env("K\0EY", "val").spawn(), inval());
+    assert_eq!(Command::new("/bin/sh").env("KEY", "v\0al").spawn(), inval());
+}
+
+// Fake syscall copying the output without a terminator when it fills the
+// buffer exactly, like a kernel leaving out the NUL
+fn fill_buf(output: &'static [u8]) -> impl FnMut(*mut i8, usize) -> c_int {
+    move |buf, buf_len| {
+        if output.len() > buf_len {
+            return wasi::ERRNO_NOBUFS.raw().into();
+        }
+        let buf = unsafe { std::slice::from_raw_parts_mut(buf as *mut u8, buf_len) };
+        buf.fill(b'#');
+        buf[..output.len()].copy_from_slice(output);
+        if output.len() < buf_len {
+            buf[output.len()] = 0;
+        }
+        0
+    }
+}
+
+#[test]
+fn truncate_at_nul_without_terminator() {
+    let mut buf = b"/full/buffer".to_vec();
+    truncate_at_nul(&mut buf);
+    assert_eq!(buf, b"/full/buffer");
+
+    let mut buf = b"/short\0garbage".to_vec();
+    truncate_at_nul(&mut buf);
+    assert_eq!(buf, b"/short");
+}
+
+#[test]
+fn growing_buf_filled_completely() {
+    static FULL: [u8; DEFAULT_INITIAL_BUF_SIZE] = [b'a'; DEFAULT_INITIAL_BUF_SIZE];
+    assert_eq!(read_growing_buf(fill_buf(&FULL)).unwrap(), FULL);
+
+    // the second attempt fills the doubled buffer exactly
+    static LONG: [u8; DEFAULT_INITIAL_BUF_SIZE * 2] = [b'b'; DEFAULT_INITIAL_BUF_SIZE * 2];
+    assert_eq!(read_growing_buf(fill_buf(&LONG)).unwrap(), LONG);
+
+    assert_eq!(read_growing_buf(fill_buf(b"/tmp")).unwrap(), b"/tmp");
+
+    let mut buf = Vec::new();
+    grow_buf_with_limits(&mut buf, 4, 4, fill_buf(b"/tmp")).unwrap();
+    truncate_at_nul(&mut buf);
+    assert_eq!(buf, b"/tmp");
+    assert_eq!(
+        grow_buf_with_limits(&mut buf, 4, 4, fill_buf(b"/tmp/x")),
+        Err(WasiExtError::Errno(wasi::ERRNO_NAMETOOLONG))
+    );
+}
+
+#[test]
+fn mount_options_data() {
+    let mut options = MountOptions::new();
+    options.opt("uid", "1000").flag("ro").opt("mode", "0755");
+    assert_eq!(options.to_data().unwrap(), "uid=1000,ro,mode=0755");
+    assert_eq!(MountOptions::new().to_data().unwrap(), "");
+}
+
+#[test]
+fn mount_options_reject_separators() {
+    let invalid = [
+        MountOptions::new().opt("uid", "1000,gid=0").clone(),
+        MountOptions::new().opt("uid", "a=b").clone(),
+        MountOptions::new().opt("u,id", "1000").clone(),
+        MountOptions::new().opt("uid=0", "1000").clone(),
+        MountOptions::new().flag("ro,exec").clone(),
+        MountOptions::new().flag("").clone(),
+        MountOptions::new().opt("", "1000").clone(),
+    ];
+    for options in invalid {
+        assert_eq!(options.to_data(), inval(), "{options:?}");
+        assert_eq!(
+            mount_with_flags("/dev", "/mnt", "ext2", MountFlags::empty(), &options),
+            inval()
+        );
+        assert!(matches!(
+            mount_scoped("/dev", "/mnt", "ext2", MountFlags::empty(), &options),
+            Err(WasiExtError::Errno(wasi::ERRNO_INVAL))
+        ));
+    }
+}
+
+#[test]
+fn cfsetspeed_accepts_only_baud_constants() {
+    let mut tio: termios::termios = unsafe { mem::zeroed() };
+    tio.c_cflag = termios::CS8;
+    for speed in [
+        termios::B0,
+        termios::B9600,
+        termios::B38400,
+        termios::B57600,
+    ] {
+        cfsetospeed(&mut tio, speed).unwrap();
+        assert_eq!(cfgetospeed(&tio), speed);
+        assert_eq!(tio.c_cflag & !termios::CBAUD, termios::CS8);
+    }
+    cfsetispeed(&mut tio, termios::B4000000).unwrap();
+    assert_eq!(cfgetispeed(&tio), termios::B4000000);
+
+    // the CBAUDEX bit alone is within CBAUD but not a speed
+    for speed in [0o010000, 0o020000, termios::CBAUD + 1, u32::MAX] {
+        assert_eq!(cfsetospeed(&mut tio, speed), inval(), "{speed:o}");
+        assert_eq!(cfsetispeed(&mut tio, speed), inval(), "{speed:o}");
+    }
+    assert_eq!(cfgetospeed(&tio), termios::B4000000);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_roundtrip_redirects() {
+    let redirects = [
+        Redirect::Read(0, String::from("in")),
+        Redirect::Write(1, String::from("out")),
+        Redirect::Append(2, String::from("log")),
+        Redirect::ReadWrite(3, String::from("file")),
+        Redirect::ReadWriteAppend(4, String::from("file")),
+        Redirect::PipeIn(5),
+        Redirect::PipeOut(6),
+        Redirect::Duplicate {
+            fd_src: 1,
+            fd_dst: 2,
+        },
+        Redirect::Close(7),
+    ];
+    for redirect in redirects {
+        let json = serde_json::to_string(&redirect).unwrap();
+        assert_eq!(serde_json::from_str::<Redirect>(&json).unwrap(), redirect);
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_roundtrip_termios_state() {
+    let mut tio: termios::termios = unsafe { mem::zeroed() };
+    tio.c_iflag = termios::ICRNL;
+    tio.c_oflag = termios::OPOST;
+    tio.c_cflag = termios::CS8 | termios::B38400;
+    tio.c_lflag = termios::ECHO | termios::ICANON;
+    tio.c_line = 1;
+    for (i, cc) in tio.c_cc.iter_mut().enumerate() {
+        *cc = i as termios::cc_t + 1;
+    }
+    tio.__c_ispeed = termios::B9600;
+    tio.__c_ospeed = termios::B38400;
+
+    let state = TermiosState::from(tio);
+    let json = serde_json::to_string(&state).unwrap();
+    let restored = serde_json::from_str::<TermiosState>(&json).unwrap();
+    assert_eq!(restored, state);
+    assert_eq!(TermiosState::from(termios::termios::from(restored)), state);
+    assert_eq!(restored.c_cc, tio.c_cc);
+    assert_eq!(
+        (restored.ispeed, restored.ospeed),
+        (termios::B9600, termios::B38400)
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_roundtrip_winsize_state() {
+    let winsize = termios::winsize {
+        ws_row: 24,
+        ws_col: 80,
+        ws_xpixel: 640,
+        ws_ypixel: 480,
+    };
+    let state = WinsizeState::from(winsize);
+    let json = serde_json::to_string(&state).unwrap();
+    let restored = serde_json::from_str::<WinsizeState>(&json).unwrap();
+    assert_eq!(restored, state);
+    let restored = termios::winsize::from(restored);
+    assert_eq!(
+        (
+            restored.ws_row,
+            restored.ws_col,
+            restored.ws_xpixel,
+            restored.ws_ypixel
+        ),
+        (24, 80, 640, 480)
+    );
+}
+
+#[test]
+fn validate_redirects_empty_path() {
+    let redirects = [Redirect::Write(1, String::new())];
+    assert_eq!(
+        validate_redirects(&redirects),
+        Err(RedirectError {
+            index: 0,
+            error: WasiExtError::Errno(wasi::ERRNO_INVAL)
+        })
+    );
+}
+
+#[test]
+fn validate_redirects_nul_in_path() {
+    let redirects = [
+        Redirect::Close(3),
+        Redirect::Append(2, String::from("lo\0g")),
+    ];
+    assert_eq!(
+        validate_redirects(&redirects),
+        Err(RedirectError {
+            index: 1,
+            error: WasiExtError::Errno(wasi::ERRNO_INVAL)
+        })
+    );
+}
+
+// An fd closed by an earlier redirect is reported at the redirect using it
+#[test]
+fn validate_redirects_reports_index() {
+    let redirects = [
+        Redirect::Write(5, String::from("out")),
+        Redirect::Close(5),
+        Redirect::Duplicate {
+            fd_src: 5,
+            fd_dst: 1,
+        },
+    ];
+    let err = validate_redirects(&redirects).unwrap_err();
+    assert_eq!(err.index, 2);
+    assert_eq!(err.error, WasiExtError::Errno(wasi::ERRNO_BADF));
+    assert_eq!(err.to_string(), format!("redirect 2: {}", err.error));
+    let mut command = Command::new("cat");
+    for redirect in redirects {
+        command.redirect(redirect);
+    }
+    assert_eq!(command.validate_redirects(true).spawn(), Err(err.error));
+}
+
+#[cfg(feature = "mock")]
+mod host {
+    use std::fs::File;
+    use std::io::{Read, Write};
+    use std::os::fd::FromRawFd;
+    use std::os::unix::process::{CommandExt, ExitStatusExt};
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex, MutexGuard, Once};
+    use std::{panic, process, ptr, thread};
+
+    use super::inval;
+    use crate::*;
+
+    static CHILDREN: Mutex<()> = Mutex::new(());
+
+    // Taken by tests spawning processes so that waiting for any child only
+    // sees the children of the current test
+    fn lock_children() -> MutexGuard<'static, ()> {
+        CHILDREN.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn assert_no_children() {
+        assert_eq!(
+            waitpid(Pid::from_raw(-1), WaitOptions::WNOHANG),
+            Err(WasiExtError::Errno(wasi::ERRNO_CHILD))
+        );
+    }
+
+    // Never opened by the tests, checking a closed low fd could race with
+    // another test getting it
+    const CLOSED_FD: Fd = 900;
+
+    fn errno<T>(errno: wasi::Errno) -> Result<T, WasiExtError> {
+        Err(WasiExtError::Errno(errno))
+    }
+
+    // Unique per test, the tests run in parallel in one process
+    fn temp_path(name: &str) -> String {
+        let dir = env::temp_dir();
+        let path = dir.join(format!("wasi_ext_lib-{}-{name}", std::process::id()));
+        String::from(path.to_str().unwrap())
+    }
+
+    fn temp_file(name: &str, contents: &[u8]) -> String {
+        let path = temp_path(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn temp_dir(name: &str) -> String {
+        let path = temp_path(name);
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir(&path).unwrap();
+        path
+    }
+
+    // Takes ownership of the fd
+    fn read_to_string(fd: Fd) -> String {
+        let mut contents = String::new();
+        unsafe { File::from_raw_fd(fd as i32) }
+            .read_to_string(&mut contents)
+            .unwrap();
+        contents
+    }
+
+    fn fstat_ino(path: &str) -> u64 {
+        let file = File::open(path).unwrap();
+        fstat(file.as_raw_fd() as Fd).unwrap().ino()
+    }
+
+    // Runs f in a forked copy of the test process and fails if it panics
+    // there. Only the calling thread is copied, so f can change process wide
+    // state like the cwd, the umask or stdio without other tests seeing it.
+    // Paths have to be built beforehand, env locks may be held by threads
+    // that were not copied
+    fn in_child<F: FnOnce()>(f: F) {
+        let _children = lock_children();
+        let pid = unsafe { libc::fork() };
+        assert!(pid >= 0, "fork failed");
+        if pid == 0 {
+            let code = match panic::catch_unwind(panic::AssertUnwindSafe(f)) {
+                Ok(()) => 0,
+                Err(payload) => {
+                    // the test harness only captures output of the parent
+                    let message = payload.downcast_ref::<String>();
+                    let message = message.map_or("child panicked", |m| m.as_str());
+                    unsafe { libc::write(2, message.as_ptr().cast(), message.len()) };
+                    1
+                }
+            };
+            unsafe { libc::_exit(code) }
+        }
+        let pid = Pid::from_raw(pid);
+        assert_eq!(
+            waitpid(pid, WaitOptions::empty()),
+            Ok((pid, WaitStatus::Exited(0)))
+        );
+    }
+
+    extern "C" fn ignore_signal(_: c_int) {}
+
+    struct Target(libc::pthread_t);
+
+    // pthread_t is a pointer on some hosts, it is only passed to pthread_kill
+    unsafe impl Send for Target {}
+
+    // Keeps sending SIGUSR1 to the calling thread until dropped, so blocking
+    // calls made there fail with EINTR no matter when they start. The handler
+    // is installed without SA_RESTART
+    struct Interrupter {
+        done: Arc<AtomicBool>,
+        thread: Option<thread::JoinHandle<()>>,
+    }
+
+    fn interrupt_repeatedly() -> Interrupter {
+        static HANDLER: Once = Once::new();
+        HANDLER.call_once(|| unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = ignore_signal as extern "C" fn(c_int) as usize;
+            libc::sigaction(libc::SIGUSR1, &action, ptr::null_mut());
+        });
+        let target = Target(unsafe { libc::pthread_self() });
+        let done = Arc::new(AtomicBool::new(false));
+        let stop = done.clone();
+        let thread = thread::spawn(move || {
+            let target = target;
+            while !stop.load(Ordering::SeqCst) {
+                unsafe { libc::pthread_kill(target.0, libc::SIGUSR1) };
+                thread::sleep(Duration::from_millis(20));
+            }
+        });
+        Interrupter {
+            done,
+            thread: Some(thread),
+        }
+    }
+
+    impl Drop for Interrupter {
+        fn drop(&mut self) {
+            self.done.store(true, Ordering::SeqCst);
+            if let Some(thread) = self.thread.take() {
+                thread.join().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn getcwd_matches_host() {
+        let cwd = env::current_dir().unwrap();
+        assert_eq!(getcwd().unwrap(), cwd.to_str().unwrap());
+        assert_eq!(getcwd_os().unwrap(), cwd.as_os_str());
+    }
+
+    #[test]
+    fn chdir_variants() {
+        let dir = fs::canonicalize(temp_dir("chdir")).unwrap();
+        let file = temp_file("chdir_file", b"");
+        let dir_str = String::from(dir.to_str().unwrap());
+        in_child(|| {
+            chdir(&dir).unwrap();
+            assert_eq!(getcwd().unwrap(), dir_str);
+            chdir_cstr(c"/").unwrap();
+            assert_eq!(getcwd().unwrap(), "/");
+
+            let dir_fd = open_dir(&dir_str).unwrap();
+            fchdir(dir_fd).unwrap();
+            assert_eq!(getcwd().unwrap(), dir_str);
+            assert_eq!(env::current_dir().unwrap(), dir);
+            close(dir_fd).unwrap();
+
+            let file_fd = open(&file, OFlags::READ, 0).unwrap();
+            assert_eq!(fchdir(file_fd), errno(wasi::ERRNO_NOTDIR));
+            assert_eq!(chdir(&file), errno(wasi::ERRNO_NOTDIR));
+            close(file_fd).unwrap();
+            assert_eq!(fchdir(CLOSED_FD), errno(wasi::ERRNO_BADF));
+            assert_eq!(getcwd().unwrap(), dir_str);
+        });
+        fs::remove_dir(&dir).unwrap();
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn io_errors_use_wasi_numbering() {
+        assert_eq!(
+            chdir("/nonexistent/wasi_ext_lib"),
+            Err(WasiExtError::Errno(wasi::ERRNO_NOENT))
+        );
+    }
+
+    #[test]
+    fn env_roundtrip() {
+        let key = "WASI_EXT_LIB_TEST_ENV";
+        set_env(key, Some("value")).unwrap();
+        assert_eq!(get_env(key).unwrap().as_deref(), Some("value"));
+        assert!(environ()
+            .unwrap()
+            .iter()
+            .any(|(k, v)| k == key && v == "value"));
+
+        set_env(key, None).unwrap();
+        assert_eq!(get_env(key).unwrap(), None);
+        assert!(!environ().unwrap().iter().any(|(k, _)| k == key));
+    }
+
+    #[test]
+    fn pipe_transfers_data() {
+        let (read_fd, write_fd) = pipe().unwrap();
+        let mut writer = unsafe { File::from_raw_fd(write_fd as i32) };
+        writer.write_all(b"through the pipe").unwrap();
+        drop(writer);
+        assert_eq!(read_to_string(read_fd), "through the pipe");
+    }
+
+    #[test]
+    fn fcntl_cloexec_flag() {
+        let (read_fd, write_fd) = pipe().unwrap();
+        assert_eq!(fcntl(read_fd, FcntlCommand::F_GETFD).unwrap(), 0);
+
+        let flags = WASI_EXT_FDFLAG_CLOEXEC;
+        fcntl(read_fd, FcntlCommand::F_SETFD { flags }).unwrap();
+        assert_eq!(
+            fcntl(read_fd, FcntlCommand::F_GETFD).unwrap(),
+            WASI_EXT_FDFLAG_CLOEXEC as i32
+        );
+
+        fcntl(read_fd, FcntlCommand::F_SETFD { flags: 0 }).unwrap();
+        assert_eq!(fcntl(read_fd, FcntlCommand::F_GETFD).unwrap(), 0);
+
+        close(read_fd).unwrap();
+        close(write_fd).unwrap();
+        assert_eq!(
+            fcntl(read_fd, FcntlCommand::F_GETFD),
+            Err(WasiExtError::Errno(wasi::ERRNO_BADF))
+        );
+    }
+
+    // The child checks whether the fd passed as $1 is open in it
+    fn open_in_child(fd: Fd) -> bool {
+        let (code, _) = Command::new("sh")
+            .args(["-c", "[ -e /dev/fd/$1 ]", "sh", &fd.to_string()])
+            .spawn()
+            .unwrap();
+        code == 0
+    }
+
+    #[test]
+    fn cloexec_fd_not_inherited() {
+        let _children = lock_children();
+        let (read_fd, write_fd) = pipe().unwrap();
+        let cloexec = dup_cloexec(read_fd).unwrap();
+        assert!(open_in_child(read_fd));
+        assert!(!open_in_child(cloexec));
+
+        let (cloexec_read, cloexec_write) = pipe_with_flags(WASI_EXT_FDFLAG_CLOEXEC).unwrap();
+        assert!(!open_in_child(cloexec_read));
+        assert!(!open_in_child(cloexec_write));
+        for fd in [read_fd, write_fd, cloexec, cloexec_read, cloexec_write] {
+            close(fd).unwrap();
+        }
+    }
+
+    #[test]
+    fn close_range_closes_fds() {
+        let (read_fd, write_fd) = pipe().unwrap();
+        // far above the fds of the other tests
+        let lo = fcntl(read_fd, FcntlCommand::F_DUPFD { min_fd_num: 600 }).unwrap() as Fd;
+        let hi = fcntl(write_fd, FcntlCommand::F_DUPFD { min_fd_num: lo }).unwrap() as Fd;
+
+        close_range(lo, hi, CloseRangeFlags::CLOEXEC).unwrap();
+        for fd in [lo, hi] {
+            assert_eq!(
+                fcntl(fd, FcntlCommand::F_GETFD),
+                Ok(WASI_EXT_FDFLAG_CLOEXEC as i32)
+            );
+        }
+        close_range(lo, hi, CloseRangeFlags::empty()).unwrap();
+        for fd in [lo, hi] {
+            assert_eq!(fcntl(fd, FcntlCommand::F_GETFD), errno(wasi::ERRNO_BADF));
+        }
+        assert_eq!(close_range(hi, lo, CloseRangeFlags::empty()), inval());
+
+        // the originals are outside the range
+        assert_eq!(fcntl(read_fd, FcntlCommand::F_GETFD), Ok(0));
+        close(read_fd).unwrap();
+        close(write_fd).unwrap();
+    }
+
+    #[test]
+    fn list_open_fds_tracks_close() {
+        let (read_fd, write_fd) = pipe().unwrap();
+        let fd = fcntl(read_fd, FcntlCommand::F_DUPFD { min_fd_num: 700 }).unwrap() as Fd;
+        let fds = list_open_fds().unwrap();
+        assert!(fds.contains(&fd) && fds.contains(&read_fd) && fds.contains(&write_fd));
+        assert!(fds.windows(2).all(|pair| pair[0] < pair[1]));
+
+        close(fd).unwrap();
+        assert!(!list_open_fds().unwrap().contains(&fd));
+        close(read_fd).unwrap();
+        close(write_fd).unwrap();
+    }
+
+    #[test]
+    fn spawn_foreground_exit_code() {
+        let _children = lock_children();
+        let (code, _) = Command::new("sh").args(["-c", "exit 3"]).spawn().unwrap();
+        assert_eq!(code, 3);
+
+        let (code, _) = Command::new("sh")
+            .args(["-c", "kill -TERM $$"])
+            .spawn()
+            .unwrap();
+        assert_eq!(exit_signal(code), Some(wasi::SIGNAL_TERM));
+    }
+
+    #[test]
+    fn spawn_background_and_waitpid() {
+        let _children = lock_children();
+        let (read_fd, write_fd) = pipe().unwrap();
+        let (_, pid) = Command::new("sh")
+            .args(["-c", "echo $GREETING; exit 5"])
+            .env("GREETING", "hello")
+            .stdout(Stdio::Pipe(write_fd))
+            .background(true)
+            .spawn()
+            .unwrap();
+        close(write_fd).unwrap();
+
+        assert_eq!(
+            waitpid(pid, WaitOptions::empty()).unwrap(),
+            (pid, WaitStatus::Exited(5))
+        );
+        assert_eq!(read_to_string(read_fd), "hello\n");
+    }
+
+    // Every argument and variable is a separate allocation whose pointer is
+    // handed to the kernel, they all have to survive until the call returns
+    #[test]
+    fn spawn_many_args_and_large_env() {
+        let _children = lock_children();
+        let args = (1..=2000)
+            .map(|i| format!("arg{i}"))
+            .collect::<Vec<String>>();
+        let value = "x".repeat(1024);
+        let mut command = Command::new("sh");
+        command
+            .args([
+                "-c",
+                "[ $# -eq 2000 ] && [ \"$1\" = arg1 ] && [ \"${2000}\" = arg2000 ] && \
+                 [ \"$WASI_EXT_LIB_VAR_0\" = \"$WASI_EXT_LIB_VAR_199\" ] && \
+                 [ ${#WASI_EXT_LIB_VAR_199} -eq 1024 ]",
+                "sh",
+            ])
+            .args(&args)
+            .envs((0..200).map(|i| (format!("WASI_EXT_LIB_VAR_{i}"), value.as_str())));
+        assert_eq!(command.spawn().map(|(code, _)| code), Ok(0));
+    }
+
+    #[test]
+    fn output_captures_stdout_and_stderr() {
+        let _children = lock_children();
+        let output = Command::new("sh")
+            .args(["-c", "echo out; echo err >&2; exit 4"])
+            .output()
+            .unwrap();
+        assert_eq!(
+            output,
+            Output {
+                status: WaitStatus::Exited(4),
+                stdout: b"out\n".to_vec(),
+                stderr: b"err\n".to_vec(),
+            }
+        );
+
+        let output = crate::output("sh", ["-c", "echo $VAR"], [("VAR", "set")]).unwrap();
+        assert_eq!(output.status, WaitStatus::Exited(0));
+        assert_eq!(output.stdout, b"set\n");
+        assert!(output.stderr.is_empty());
+        assert_no_children();
+    }
+
+    #[test]
+    fn output_reaps_child_on_error() {
+        let _children = lock_children();
+        // polling the pipes fails with EINTR, the child is killed and reaped
+        let interrupter = interrupt_repeatedly();
+        let result = Command::new("sh")
+            .args(["-c", "echo out; echo err >&2; sleep 10"])
+            .output();
+        drop(interrupter);
+        assert_eq!(result, errno(wasi::ERRNO_INTR));
+        assert_no_children();
+    }
+
+    #[test]
+    fn sleep_reports_interruption() {
+        sleep(Duration::from_millis(10)).unwrap();
+
+        let interrupter = interrupt_repeatedly();
+        let result = sleep(Duration::from_secs(10));
+        drop(interrupter);
+        match result {
+            Err(SleepError::Interrupted(remaining)) => {
+                assert!(!remaining.is_zero() && remaining < Duration::from_secs(10))
+            }
+            result => panic!("{result:?}"),
+        }
+        let error = SleepError::Interrupted(Duration::from_secs(1));
+        assert_eq!(
+            WasiExtError::from(error),
+            WasiExtError::Errno(wasi::ERRNO_INTR)
+        );
+    }
+
+    #[test]
+    fn spawn_missing_binary() {
+        assert_eq!(
+            Command::new("/nonexistent/wasi_ext_lib").spawn(),
+            Err(WasiExtError::Errno(wasi::ERRNO_NOENT))
+        );
+    }
+
+    #[test]
+    fn child_wait_and_try_wait() {
+        let _children = lock_children();
+        let mut child = Command::new("sh")
+            .args(["-c", "read line; exit 7"])
+            .stdin(Stdio::Null)
+            .spawn_child()
+            .unwrap();
+        assert_eq!(child.wait(), Ok(WaitStatus::Exited(7)));
+        // cached, the pid is not waited for again
+        assert_eq!(child.wait(), Ok(WaitStatus::Exited(7)));
+        assert_eq!(child.try_wait(), Ok(Some(WaitStatus::Exited(7))));
+        assert_eq!(child.kill(wasi::SIGNAL_KILL), Ok(()));
+
+        let mut child = Command::new("sleep").arg("10").spawn_child().unwrap();
+        assert_eq!(child.try_wait(), Ok(None));
+        child.kill(wasi::SIGNAL_KILL).unwrap();
+        assert_eq!(child.wait(), Ok(WaitStatus::Signaled(wasi::SIGNAL_KILL)));
+        assert_no_children();
+    }
+
+    #[test]
+    fn waitpid_stopped_and_continued() {
+        let _children = lock_children();
+        let mut child = Command::new("sleep").arg("10").spawn_child().unwrap();
+        let pid = child.id();
+
+        kill(pid, wasi::SIGNAL_STOP).unwrap();
+        assert_eq!(
+            waitpid(pid, WaitOptions::WUNTRACED),
+            Ok((pid, WaitStatus::Stopped(wasi::SIGNAL_STOP)))
+        );
+        kill(pid, wasi::SIGNAL_CONT).unwrap();
+        assert_eq!(
+            waitpid(pid, WaitOptions::WCONTINUED),
+            Ok((pid, WaitStatus::Continued))
+        );
+        assert_eq!(child.try_wait(), Ok(None));
+
+        child.kill(wasi::SIGNAL_TERM).unwrap();
+        assert_eq!(child.wait(), Ok(WaitStatus::Signaled(wasi::SIGNAL_TERM)));
+        assert_no_children();
+    }
+
+    #[test]
+    fn kill_raw_terminates() {
+        let _children = lock_children();
+        let (_, pid) = Command::new("sleep")
+            .arg("10")
+            .background(true)
+            .spawn()
+            .unwrap();
+        assert_eq!(kill_raw(pid, 99), inval());
+        kill_raw(pid, wasi::SIGNAL_TERM.raw() as i32).unwrap();
+        assert_eq!(
+            waitpid(pid, WaitOptions::empty()),
+            Ok((pid, WaitStatus::Signaled(wasi::SIGNAL_TERM)))
+        );
+    }
+
+    #[test]
+    fn kill_process_group() {
+        let _children = lock_children();
+        let sleep_in_group = |pgid: i32| {
+            process::Command::new("sleep")
+                .arg("10")
+                .process_group(pgid)
+                .spawn()
+                .unwrap()
+        };
+        let mut leader = sleep_in_group(0);
+        let pgid = Pid::from_raw(leader.id() as i32);
+        let mut member = sleep_in_group(pgid.into_raw());
+        assert_eq!(getpgid(Pid::from_raw(member.id() as i32)), Ok(pgid));
+
+        kill(Pid::from_raw(-pgid.into_raw()), wasi::SIGNAL_TERM).unwrap();
+        for child in [&mut leader, &mut member] {
+            assert_eq!(child.wait().unwrap().signal(), Some(libc::SIGTERM));
+        }
+    }
+
+    #[test]
+    fn getppid_in_child() {
+        let parent = getpid().unwrap();
+        assert_eq!(getppid(), Ok(Pid::from_raw(unsafe { libc::getppid() })));
+        in_child(|| assert_eq!(getppid(), Ok(parent)));
+    }
+
+    #[test]
+    fn setsid_makes_caller_leader() {
+        in_child(|| {
+            let pid = getpid().unwrap();
+            assert_ne!(getpgrp(), Ok(pid));
+            assert_eq!(setsid(), Ok(pid));
+            assert_eq!(getsid(Pid::from_raw(0)), Ok(pid));
+            assert_eq!(getpgrp(), Ok(pid));
+            // already a group leader
+            assert_eq!(setsid(), errno(wasi::ERRNO_PERM));
+        });
+    }
+
+    #[test]
+    fn umask_masks_created_modes() {
+        let file = temp_path("umask_file");
+        let dir = temp_path("umask_dir");
+        let parent = temp_dir("umask_parent");
+        in_child(|| {
+            umask(0o077).unwrap();
+            assert_eq!(umask(0o027), Ok(0o077));
+            assert_eq!(umask(0o077), Ok(0o027));
+
+            let fd = open(&file, OFlags::WRITE | OFlags::CREATE, 0o666).unwrap();
+            assert_eq!(fstat(fd).unwrap().mode() & 0o777, 0o600);
+            close(fd).unwrap();
+            // the umask is left as it was
+            assert_eq!(umask(0o077), Ok(0o077));
+
+            mkdir(&dir, 0o777).unwrap();
+            assert_eq!(stat(&dir).unwrap().mode() & 0o777, 0o700);
+
+            let dirfd = open_dir(&parent).unwrap();
+            mkdirat(dirfd, "sub", 0o755).unwrap();
+            let fd = openat(dirfd, "file", OFlags::WRITE | OFlags::CREATE, 0o644).unwrap();
+            assert_eq!(fstat(fd).unwrap().mode() & 0o777, 0o600);
+            close(fd).unwrap();
+            assert_eq!(
+                stat(&format!("{parent}/sub")).unwrap().mode() & 0o777,
+                0o700
+            );
+            close(dirfd).unwrap();
+        });
+        fs::remove_file(&file).unwrap();
+        fs::remove_dir(&dir).unwrap();
+        fs::remove_dir_all(&parent).unwrap();
+    }
+
+    #[test]
+    fn rlimit_nofile() {
+        in_child(|| {
+            let limit = getrlimit(Resource::Nofile).unwrap();
+            assert!(limit.soft <= limit.hard);
+
+            let lowered = RLimit {
+                soft: limit.soft - 1,
+                hard: limit.hard,
+            };
+            setrlimit(Resource::Nofile, lowered).unwrap();
+            assert_eq!(getrlimit(Resource::Nofile), Ok(lowered));
+            setrlimit(Resource::Nofile, limit).unwrap();
+            assert_eq!(getrlimit(Resource::Nofile), Ok(limit));
+
+            let inverted = RLimit {
+                soft: limit.soft,
+                hard: limit.soft - 1,
+            };
+            assert_eq!(setrlimit(Resource::Nofile, inverted), inval());
+        });
+    }
+
+    #[test]
+    fn record_locks_across_processes() {
+        let path = temp_file("record_lock", b"0123456789abcdefghij");
+        let fd = open(&path, OFlags::READ | OFlags::WRITE, 0).unwrap();
+        let held = RecordLock::new(LockType::Write, Whence::Set, 0, 10);
+        fcntl(fd, FcntlCommand::F_SETLK { lock: held }).unwrap();
+        // locks of the own process never conflict
+        assert_eq!(get_lock(fd, held), Ok(None));
+
+        let parent = getpid().unwrap();
+        in_child(|| {
+            let fd = open(&path, OFlags::READ | OFlags::WRITE, 0).unwrap();
+            let overlapping = RecordLock::new(LockType::Read, Whence::Set, 5, 10);
+            let conflict = get_lock(fd, overlapping).unwrap().unwrap();
+            assert_eq!(
+                (
+                    conflict.lock_type,
+                    conflict.start,
+                    conflict.len,
+                    conflict.pid
+                ),
+                (LockType::Write, 0, 10, parent)
+            );
+            assert_eq!(
+                fcntl(fd, FcntlCommand::F_GETLK { lock: overlapping }),
+                Ok(parent.into_raw())
+            );
+            assert_eq!(
+                fcntl(fd, FcntlCommand::F_SETLK { lock: overlapping }),
+                errno(wasi::ERRNO_AGAIN)
+            );
+
+            let disjoint = RecordLock::new(LockType::Write, Whence::Set, 10, 10);
+            assert_eq!(get_lock(fd, disjoint), Ok(None));
+            assert_eq!(fcntl(fd, FcntlCommand::F_SETLK { lock: disjoint }), Ok(0));
+        });
+
+        let unlock = RecordLock::new(LockType::Unlock, Whence::Set, 0, 0);
+        fcntl(fd, FcntlCommand::F_SETLK { lock: unlock }).unwrap();
+        close(fd).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn redirect_guard_restores_stdout() {
+        let path = temp_path("redirect_guard");
+        in_child(|| {
+            let before = fstat(1).unwrap();
+            {
+                let _guard = RedirectGuard::new(&[Redirect::Write(1, path.clone())]).unwrap();
+                let redirected = fstat(1).unwrap();
+                assert_eq!(redirected.ino(), fstat_ino(&path));
+                let text = b"inside\n";
+                assert_eq!(write_nonblocking(1, text), Ok(Some(text.len())));
+            }
+            let after = fstat(1).unwrap();
+            assert_eq!((after.dev(), after.ino()), (before.dev(), before.ino()));
+            assert_eq!(fs::read(&path).unwrap(), b"inside\n");
+
+            let result = with_redirects(&[Redirect::Append(1, path.clone())], || {
+                write_nonblocking(1, b"again\n")
+            });
+            assert_eq!(result, Ok(Ok(Some(6))));
+            assert_eq!(fstat(1).unwrap().ino(), before.ino());
+            assert_eq!(fs::read(&path).unwrap(), b"inside\nagain\n");
+        });
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tty_checks_on_redirected_streams() {
+        let path = temp_file("tty_streams", b"");
+        in_child(|| {
+            let fd = open(&path, OFlags::READ | OFlags::WRITE, 0).unwrap();
+            for stream in 0..3 {
+                dup2(fd, stream).unwrap();
+            }
+            assert!(!is_stdin_tty() && !is_stdout_tty() && !is_stderr_tty());
+            assert_eq!(isatty(1), Ok(false));
+
+            close(2).unwrap();
+            assert_eq!(isatty(2), errno(wasi::ERRNO_BADF));
+            assert!(!is_stderr_tty());
+        });
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn terminal_queries_on_files() {
+        let path = temp_file("tty_queries", b"");
+        let fd = open(&path, OFlags::READ, 0).unwrap();
+        assert_eq!(isatty(fd as i32), Ok(false));
+        assert_eq!(ttyname(fd), errno(wasi::ERRNO_NOTTY));
+        assert_eq!(tcgetsid(fd), errno(wasi::ERRNO_NOTTY));
+        close(fd).unwrap();
+        assert_eq!(ttyname(CLOSED_FD), errno(wasi::ERRNO_BADF));
+        assert_eq!(tcgetsid(CLOSED_FD), errno(wasi::ERRNO_BADF));
+        fs::remove_file(&path).unwrap();
+    }
+
+    // Device ioctls are rejected by the fd itself, a pipe or file is neither
+    // a fifo nor a wget device
+    #[test]
+    fn device_ioctls_on_other_fds() {
+        let (read_fd, write_fd) = pipe().unwrap();
+        let read_fd = read_fd as RawFd;
+        let notty = wasi::ERRNO_NOTTY;
+        assert_eq!(fifo_set_kernel_write(read_fd, 1), errno(notty));
+        assert_eq!(fifo_set_kernel_read(read_fd, 1), errno(notty));
+        assert_eq!(fifo_set_close_rm(read_fd, 1), errno(notty));
+        close(read_fd as Fd).unwrap();
+        close(write_fd).unwrap();
+
+        let path = temp_file("wget", b"");
+        let file = File::open(&path).unwrap();
+        let fd = file.as_raw_fd();
+        assert_eq!(wget_get_status(fd), errno(notty));
+        assert_eq!(wget_read_headers(fd), errno(notty));
+        assert_eq!(wget_read_body(fd), errno(notty));
+        drop(file);
+        let closed = CLOSED_FD as RawFd;
+        assert_eq!(wget_get_status(closed), errno(wasi::ERRNO_BADF));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn lseek_whence() {
+        let path = temp_file("lseek", b"0123456789");
+        let fd = open(&path, OFlags::READ, 0).unwrap();
+        assert_eq!(lseek(fd, 4, Whence::Set), Ok(4));
+        assert_eq!(lseek(fd, 0, Whence::Cur), Ok(4));
+        let mut buf = [0u8; 16];
+        assert_eq!(read_nonblocking(fd, &mut buf), Ok(Some(6)));
+        assert_eq!(&buf[..6], b"456789");
+
+        assert_eq!(lseek(fd, -3, Whence::End), Ok(7));
+        assert_eq!(lseek(fd, 2, Whence::Cur), Ok(9));
+        // past the end is allowed, before the start is not
+        assert_eq!(lseek(fd, 5, Whence::End), Ok(15));
+        assert_eq!(lseek(fd, -1, Whence::Set), inval());
+        close(fd).unwrap();
+
+        let (read_fd, write_fd) = pipe().unwrap();
+        assert_eq!(lseek(read_fd, 0, Whence::Cur), errno(wasi::ERRNO_SPIPE));
+        close(read_fd).unwrap();
+        close(write_fd).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn nonblocking_pipe() {
+        let flags = wasi::FDFLAGS_NONBLOCK;
+        let (read_fd, write_fd) = pipe_with_flags(flags).unwrap();
+        let mut buf = [0u8; 8];
+        assert_eq!(read_nonblocking(read_fd, &mut buf), Ok(None));
+
+        assert_eq!(write_nonblocking(write_fd, b"data"), Ok(Some(4)));
+        assert_eq!(read_nonblocking(read_fd, &mut buf), Ok(Some(4)));
+        assert_eq!(&buf[..4], b"data");
+
+        // a full pipe would block the writer
+        let chunk = [0u8; 4096];
+        while write_nonblocking(write_fd, &chunk).unwrap().is_some() {}
+        assert_eq!(write_nonblocking(write_fd, &chunk), Ok(None));
+
+        close(write_fd).unwrap();
+        let mut drained = [0u8; 4096];
+        while read_nonblocking(read_fd, &mut drained).unwrap() != Some(0) {}
+        close(read_fd).unwrap();
+        assert_eq!(
+            read_nonblocking(CLOSED_FD, &mut buf),
+            errno(wasi::ERRNO_BADF)
+        );
+    }
+
+    #[test]
+    fn statvfs_reports_filesystem() {
+        let stat = statvfs(env::temp_dir().to_str().unwrap()).unwrap();
+        assert!(stat.block_size() > 0);
+        assert!(stat.blocks() >= stat.free_blocks());
+        assert!(stat.free_blocks() >= stat.available_blocks());
+        assert!(stat.files() >= stat.free_files());
+        assert!(stat.name_max() > 0);
+        assert_eq!(
+            statvfs("/nonexistent/wasi_ext_lib").map(|stat| stat.blocks()),
+            errno(wasi::ERRNO_NOENT)
+        );
+    }
+
+    #[test]
+    fn chown_keeps_owner() {
+        let path = temp_file("chown", b"");
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        chown(&path, None, None).unwrap();
+        chown(&path, Some(uid), Some(gid)).unwrap();
+        let fd = open(&path, OFlags::READ, 0).unwrap();
+        fchown(fd, Some(uid), None).unwrap();
+        close(fd).unwrap();
+        assert_eq!(fchown(CLOSED_FD, None, Some(gid)), errno(wasi::ERRNO_BADF));
+        assert_eq!(
+            chown("/nonexistent/wasi_ext_lib", Some(uid), None),
+            errno(wasi::ERRNO_NOENT)
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn link_shares_inode() {
+        let path = temp_file("link", b"shared");
+        let linked = temp_path("link_new");
+        link(&path, &linked).unwrap();
+        let (meta, linked_meta) = (stat(&path).unwrap(), stat(&linked).unwrap());
+        assert_eq!(meta.ino(), linked_meta.ino());
+        assert_eq!((meta.nlink(), linked_meta.nlink()), (2, 2));
+        assert_eq!(fs::read(&linked).unwrap(), b"shared");
+
+        assert_eq!(link(&path, &linked), errno(wasi::ERRNO_EXIST));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(stat(&linked).unwrap().nlink(), 1);
+        assert_eq!(link(&path, &linked), errno(wasi::ERRNO_NOENT));
+        fs::remove_file(&linked).unwrap();
+    }
+
+    #[test]
+    fn truncate_grows_and_shrinks() {
+        let path = temp_file("truncate", b"0123456789");
+        truncate(&path, 4).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"0123");
+        truncate(&path, 6).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"0123\0\0");
+
+        let fd = open(&path, OFlags::WRITE, 0).unwrap();
+        ftruncate(fd, 2).unwrap();
+        assert_eq!(fstat(fd).unwrap().size(), 2);
+        ftruncate(fd, 3).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"01\0");
+        close(fd).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(truncate(&path, 0), errno(wasi::ERRNO_NOENT));
+    }
+
+    #[test]
+    fn rename_overwrites_target() {
+        let from = temp_file("rename_from", b"new");
+        let to = temp_file("rename_to", b"old");
+        let ino = fstat_ino(&from);
+        rename(&from, &to).unwrap();
+        assert_eq!(fs::read(&to).unwrap(), b"new");
+        assert_eq!(fstat_ino(&to), ino);
+        assert_eq!(
+            stat(&from).map(|meta| meta.size()),
+            errno(wasi::ERRNO_NOENT)
+        );
+
+        assert_eq!(rename(&from, &to), errno(wasi::ERRNO_NOENT));
+        assert_eq!(fs::read(&to).unwrap(), b"new");
+        fs::remove_file(&to).unwrap();
+    }
+
+    // Both fds are separate opens, so their locks conflict like those of
+    // different processes
+    #[test]
+    fn flock_contention() {
+        let path = temp_file("flock", b"");
+        let first = open(&path, OFlags::READ, 0).unwrap();
+        let second = open(&path, OFlags::READ, 0).unwrap();
+        let would_block = errno(wasi::ERRNO_AGAIN);
+
+        flock(first, FlockOp::EXCLUSIVE).unwrap();
+        assert_eq!(
+            flock(second, FlockOp::EXCLUSIVE | FlockOp::NONBLOCK),
+            would_block
+        );
+        assert_eq!(
+            flock(second, FlockOp::SHARED | FlockOp::NONBLOCK),
+            would_block
+        );
+
+        flock(first, FlockOp::SHARED).unwrap();
+        flock(second, FlockOp::SHARED | FlockOp::NONBLOCK).unwrap();
+        assert_eq!(
+            flock(first, FlockOp::EXCLUSIVE | FlockOp::NONBLOCK),
+            would_block
+        );
+
+        flock(second, FlockOp::UNLOCK).unwrap();
+        flock(first, FlockOp::EXCLUSIVE | FlockOp::NONBLOCK).unwrap();
+        close(first).unwrap();
+        flock(second, FlockOp::EXCLUSIVE | FlockOp::NONBLOCK).unwrap();
+        close(second).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn open_write_read_roundtrip() {
+        let path = temp_path("open");
+        let _ = fs::remove_file(&path);
+        assert_eq!(open(&path, OFlags::READ, 0), errno(wasi::ERRNO_NOENT));
+
+        let flags = OFlags::WRITE | OFlags::CREATE | OFlags::EXCLUSIVE;
+        let fd = open(&path, flags, 0o644).unwrap();
+        assert_eq!(write_nonblocking(fd, b"first"), Ok(Some(5)));
+        close(fd).unwrap();
+        assert_eq!(open(&path, flags, 0o644), errno(wasi::ERRNO_EXIST));
+
+        let fd = open(&path, OFlags::READ | OFlags::CLOEXEC, 0).unwrap();
+        assert_eq!(
+            fcntl(fd, FcntlCommand::F_GETFD),
+            Ok(WASI_EXT_FDFLAG_CLOEXEC as i32)
+        );
+        assert_eq!(read_to_string(fd), "first");
+
+        let fd = open(&path, OFlags::WRITE | OFlags::APPEND, 0).unwrap();
+        assert_eq!(write_nonblocking(fd, b" second"), Ok(Some(7)));
+        close(fd).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"first second");
+
+        let fd = open(&path, OFlags::WRITE | OFlags::TRUNCATE, 0).unwrap();
+        assert_eq!(fstat(fd).unwrap().size(), 0);
+        close(fd).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn open_nofollow_and_directory() {
+        let target = temp_file("nofollow_target", b"target");
+        let symlink_path = temp_path("nofollow_link");
+        let _ = fs::remove_file(&symlink_path);
+        symlink(&target, &symlink_path).unwrap();
+
+        assert_eq!(
+            open(&symlink_path, OFlags::READ | OFlags::NOFOLLOW, 0),
+            errno(wasi::ERRNO_LOOP)
+        );
+        let fd = open(&symlink_path, OFlags::READ, 0).unwrap();
+        assert_eq!(read_to_string(fd), "target");
+        let fd = open(&target, OFlags::READ | OFlags::NOFOLLOW, 0).unwrap();
+        close(fd).unwrap();
+
+        assert_eq!(
+            open(&target, OFlags::READ | OFlags::DIRECTORY, 0),
+            errno(wasi::ERRNO_NOTDIR)
+        );
+        let dir = env::temp_dir();
+        let fd = open(dir.to_str().unwrap(), OFlags::READ | OFlags::DIRECTORY, 0).unwrap();
+        assert!(fstat(fd).unwrap().is_dir());
+        close(fd).unwrap();
+        fs::remove_file(&symlink_path).unwrap();
+        fs::remove_file(&target).unwrap();
+    }
+
+    fn entry_names(entries: &[DirEntry]) -> Vec<&OsStr> {
+        let mut names = entries
+            .iter()
+            .map(|entry| entry.name())
+            .collect::<Vec<&OsStr>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn readdir_pages_through_entries() {
+        let dir = temp_dir("readdir");
+        let mut expected = vec![OsStr::new("."), OsStr::new(".."), OsStr::new("sub")];
+        let names = (0..40)
+            .map(|i| format!("entry_with_a_long_name_{i:02}"))
+            .collect::<Vec<String>>();
+        for name in &names {
+            fs::write(format!("{dir}/{name}"), b"").unwrap();
+            expected.push(OsStr::new(name));
+        }
+        fs::create_dir(format!("{dir}/sub")).unwrap();
+        expected.sort();
+
+        let entries = readdir(&dir).unwrap();
+        assert_eq!(entry_names(&entries), expected);
+        let file = entries
+            .iter()
+            .find(|entry| entry.name() == OsStr::new(&names[0]))
+            .unwrap();
+        assert_eq!(file.ino(), fstat_ino(&format!("{dir}/{}", names[0])));
+        assert_eq!(file.file_type(), wasi::FILETYPE_REGULAR_FILE);
+        let sub = entries
+            .iter()
+            .find(|entry| entry.name() == OsStr::new("sub"))
+            .unwrap();
+        assert_eq!(sub.file_type(), wasi::FILETYPE_DIRECTORY);
+
+        // 64 bytes hold only one or two entries, fewer than a header the
+        // buffer has to grow first
+        for buf_len in [64, 8] {
+            let paged = readdir_with_buf_len(Path::new(&dir), buf_len).unwrap();
+            assert_eq!(entry_names(&paged), expected, "{buf_len}");
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(readdir(&dir), errno(wasi::ERRNO_NOENT));
+    }
+
+    #[test]
+    fn at_functions() {
+        let dir = temp_dir("at_functions");
+        let file = temp_file("at_functions_file", b"");
+        let dirfd = open_dir(&dir).unwrap();
+        assert_eq!(open_dir(&file), errno(wasi::ERRNO_NOTDIR));
+
+        let fd = openat(dirfd, "file", OFlags::WRITE | OFlags::CREATE, 0o644).unwrap();
+        assert_eq!(write_nonblocking(fd, b"at"), Ok(Some(2)));
+        close(fd).unwrap();
+        assert_eq!(fs::read(format!("{dir}/file")).unwrap(), b"at");
+        mkdirat(dirfd, "sub", 0o755).unwrap();
+        assert!(stat(&format!("{dir}/sub")).unwrap().is_dir());
+        assert_eq!(mkdirat(dirfd, "sub", 0o755), errno(wasi::ERRNO_EXIST));
+
+        let fd = openat(dirfd, "sub", OFlags::READ | OFlags::DIRECTORY, 0).unwrap();
+        let inner = openat(fd, "inner", OFlags::WRITE | OFlags::CREATE, 0o644).unwrap();
+        close(inner).unwrap();
+        assert!(stat(&format!("{dir}/sub/inner")).unwrap().is_file());
+        unlinkat(fd, "inner").unwrap();
+        close(fd).unwrap();
+
+        assert_eq!(
+            openat(dirfd, "/etc/passwd", OFlags::READ, 0),
+            errno(wasi::ERRNO_NOTCAPABLE)
+        );
+        assert_eq!(unlinkat(dirfd, "sub"), errno(wasi::ERRNO_ISDIR));
+        assert_eq!(rmdirat(dirfd, "file"), errno(wasi::ERRNO_NOTDIR));
+        rmdirat(dirfd, "sub").unwrap();
+        unlinkat(dirfd, "file").unwrap();
+        assert_eq!(unlinkat(dirfd, "file"), errno(wasi::ERRNO_NOENT));
+        assert_eq!(readdir(&dir).unwrap().len(), 2);
+
+        close(dirfd).unwrap();
+        fs::remove_dir(&dir).unwrap();
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn stat_file_and_dir() {
+        let path = temp_path("stat");
+        fs::write(&path, b"12345").unwrap();
+        let meta = stat(&path).unwrap();
+        assert!(meta.is_file());
+        assert_eq!(meta.size(), 5);
+        assert_eq!(meta.ino(), fstat_ino(&path));
+        fs::remove_file(&path).unwrap();
+
+        assert!(stat(env::temp_dir().to_str().unwrap()).unwrap().is_dir());
+        assert_eq!(
+            stat(&path).map(|meta| meta.size()),
+            Err(WasiExtError::Errno(wasi::ERRNO_NOENT))
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn stat_device_numbers() {
+        let meta = stat("/dev/null").unwrap();
+        assert!(meta.is_char_device());
+        // /dev/null is 1:3 on every Linux system
+        assert_eq!(meta.rdev(), Dev::new(1, 3));
+        assert_eq!((meta.rdev().major(), meta.rdev().minor()), (1, 3));
+    }
+
+    #[test]
+    fn futimens_precision() {
+        let path = temp_path("futimens");
+        let file = File::create(&path).unwrap();
+        let atime = Duration::new(1_000_000_000, 1);
+        let mtime = Duration::new(1_234_567_890, 123_456_789);
+        futimens(
+            file.as_raw_fd() as Fd,
+            Some(Timestamp::At(UNIX_EPOCH + atime)),
+            Some(Timestamp::At(UNIX_EPOCH + mtime)),
+        )
+        .unwrap();
+        let meta = stat(&path).unwrap();
+        assert_eq!((meta.accessed(), meta.modified()), (atime, mtime));
+
+        // None keeps the timestamp, Now moves it past the old value
+        futimens(file.as_raw_fd() as Fd, Some(Timestamp::Now), None).unwrap();
+        let meta = stat(&path).unwrap();
+        assert!(meta.accessed() > atime);
+        assert_eq!(meta.modified(), mtime);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn utimes_sets_path_times() {
+        let path = temp_file("utimes", b"");
+        let atime = Duration::new(1_100_000_000, 5);
+        let mtime = Duration::new(1_200_000_000, 999_999_999);
+        utimes(
+            &path,
+            Some(Timestamp::At(UNIX_EPOCH + atime)),
+            Some((UNIX_EPOCH + mtime).into()),
+        )
+        .unwrap();
+        let meta = stat(&path).unwrap();
+        assert_eq!((meta.accessed(), meta.modified()), (atime, mtime));
+
+        utimes(&path, None, Some(Timestamp::Now)).unwrap();
+        let meta = stat(&path).unwrap();
+        assert_eq!(meta.accessed(), atime);
+        assert!(meta.modified() > mtime);
+
+        // a directory is updated through itself
+        let dir = temp_dir("utimes_dir");
+        utimes(&dir, Some(Timestamp::At(UNIX_EPOCH + atime)), None).unwrap();
+        assert_eq!(stat(&dir).unwrap().accessed(), atime);
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_dir(&dir).unwrap();
+        assert_eq!(
+            utimes(&path, Some(Timestamp::Now), None),
+            errno(wasi::ERRNO_NOENT)
+        );
+    }
+
+    fn redirected(redirect: Redirect, script: &str) {
+        let (code, _) = Command::new("sh")
+            .args(["-c", script])
+            .redirect(redirect)
+            .spawn()
+            .unwrap();
+        assert_eq!(code, 0, "{script}");
+    }
+
+    #[test]
+    fn redirect_modes_after_spawn() {
+        let _children = lock_children();
+        let path = temp_path("redirect_modes");
+        let reset = || fs::write(&path, b"0123456789").unwrap();
+        let contents = || fs::read_to_string(&path).unwrap();
+        let write_ab = "printf ab >&3";
+
+        reset();
+        redirected(Redirect::Write(3, path.clone()), write_ab);
+        assert_eq!(contents(), "ab");
+        reset();
+        redirected(Redirect::Append(3, path.clone()), write_ab);
+        assert_eq!(contents(), "0123456789ab");
+        reset();
+        redirected(Redirect::ReadWrite(3, path.clone()), write_ab);
+        assert_eq!(contents(), "ab23456789");
+        reset();
+        redirected(Redirect::ReadWriteAppend(3, path.clone()), write_ab);
+        assert_eq!(contents(), "0123456789ab");
+
+        // reading starts at offset 0 even when writes go to the end
+        for redirect in [
+            Redirect::Read(0, path.clone()),
+            Redirect::ReadWrite(0, path.clone()),
+            Redirect::ReadWriteAppend(0, path.clone()),
+        ] {
+            reset();
+            let output = Command::new("cat").redirect(redirect).output().unwrap();
+            assert_eq!(output.stdout, b"0123456789");
+            assert_eq!(contents(), "0123456789");
+        }
+
+        // a duplicate shares the file offset with the parent
+        reset();
+        let fd = open(&path, OFlags::READ | OFlags::WRITE, 0).unwrap();
+        lseek(fd, 4, Whence::Set).unwrap();
+        redirected(
+            Redirect::Duplicate {
+                fd_src: fd,
+                fd_dst: 3,
+            },
+            write_ab,
+        );
+        assert_eq!(contents(), "0123ab6789");
+        assert_eq!(lseek(fd, 0, Whence::Cur), Ok(6));
+        close(fd).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn validate_redirects_missing_read_target() {
+        let missing = String::from("/nonexistent/wasi_ext_lib");
+        let redirects = [
+            Redirect::Write(1, missing.clone()),
+            Redirect::Read(0, missing),
+        ];
+        assert_eq!(
+            validate_redirects(&redirects),
+            Err(RedirectError {
+                index: 1,
+                error: WasiExtError::Errno(wasi::ERRNO_NOENT)
+            })
+        );
+    }
+
+    #[test]
+    fn validate_redirects_bad_fd() {
+        let (read_fd, write_fd) = pipe().unwrap();
+        let valid = [
+            Redirect::PipeIn(read_fd),
+            Redirect::Duplicate {
+                fd_src: write_fd,
+                fd_dst: 1,
+            },
+            Redirect::Duplicate {
+                fd_src: 1,
+                fd_dst: 2,
+            },
+        ];
+        assert_eq!(validate_redirects(&valid), Ok(()));
+        close(read_fd).unwrap();
+        close(write_fd).unwrap();
+
+        for redirect in [
+            Redirect::PipeIn(CLOSED_FD),
+            Redirect::PipeOut(CLOSED_FD),
+            Redirect::Duplicate {
+                fd_src: CLOSED_FD,
+                fd_dst: 0,
+            },
+        ] {
+            assert_eq!(
+                validate_redirects(&[Redirect::Close(5), redirect.clone()]),
+                Err(RedirectError {
+                    index: 1,
+                    error: WasiExtError::Errno(wasi::ERRNO_BADF)
+                }),
+                "{redirect:?}"
+            );
+        }
+
+        // spawn only checks when asked to
+        let _children = lock_children();
+        let mut command = Command::new("true");
+        command.redirect(Redirect::PipeIn(CLOSED_FD));
+        assert_eq!(
+            command.validate_redirects(true).spawn(),
+            errno(wasi::ERRNO_BADF)
+        );
+    }
+
+    #[test]
+    fn getrandom_fills_buffer() {
+        let mut first = [0u8; 64];
+        let mut second = [0u8; 64];
+        getrandom(&mut first).unwrap();
+        getrandom(&mut second).unwrap();
+        assert_ne!(first, second);
+        assert!(first.iter().any(|b| *b != 0));
+        getrandom(&mut []).unwrap();
+    }
+
+    #[test]
+    fn mount_accepts_paths() {
+        let target = PathBuf::from(temp_dir("mount"));
+        let file = temp_file("mount_file", b"");
+        mount(PathBuf::from("/dev/null"), &target, "ramfs", 0, "").unwrap();
+        assert_eq!(
+            mount("/dev/null", target.as_path(), "ramfs", 0, ""),
+            errno(wasi::ERRNO_BUSY)
+        );
+        umount(target.to_str().unwrap()).unwrap();
+        assert_eq!(umount(target.to_str().unwrap()), inval());
+        assert_eq!(
+            mount("/dev/null", Path::new(&file), "ramfs", 0, ""),
+            errno(wasi::ERRNO_NOTDIR)
+        );
+        fs::remove_dir(&target).unwrap();
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn mount_guard_unmounts() {
+        let target = temp_dir("mount_guard");
+        let options = MountOptions::new();
+        let scoped = || mount_scoped("/dev/null", &target, "ramfs", MountFlags::empty(), &options);
+
+        let guard = scoped().unwrap();
+        assert!(matches!(
+            scoped(),
+            Err(WasiExtError::Errno(wasi::ERRNO_BUSY))
+        ));
+        drop(guard);
+        assert_eq!(umount(&target), inval());
+
+        scoped().unwrap().unmount().unwrap();
+        assert_eq!(umount(&target), inval());
+
+        scoped().unwrap().leak();
+        umount(&target).unwrap();
+        fs::remove_dir(&target).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn memfd_create_anonymous_file() {
+        let fd = memfd_create("wasi_ext_lib", MemfdFlags::CLOEXEC).unwrap();
+        assert_eq!(
+            fcntl(fd, FcntlCommand::F_GETFD),
+            Ok(WASI_EXT_FDFLAG_CLOEXEC as i32)
+        );
+        assert_eq!(write_nonblocking(fd, b"in memory"), Ok(Some(9)));
+        let meta = fstat(fd).unwrap();
+        assert!(meta.is_file());
+        assert_eq!(meta.size(), 9);
+        lseek(fd, 0, Whence::Set).unwrap();
+        assert_eq!(read_to_string(fd), "in memory");
+
+        let fd = memfd_create("wasi_ext_lib", MemfdFlags::empty()).unwrap();
+        assert_eq!(fcntl(fd, FcntlCommand::F_GETFD), Ok(0));
+        close(fd).unwrap();
+        assert_eq!(memfd_create("wasi\0ext", MemfdFlags::empty()), inval());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn copy_file_range_advances_offsets() {
+        let src_path = temp_file("copy_src", b"0123456789");
+        let dst_path = temp_file("copy_dst", b"");
+        let src = open(&src_path, OFlags::READ, 0).unwrap();
+        let dst = open(&dst_path, OFlags::WRITE, 0).unwrap();
+
+        assert_eq!(copy_file_range(src, dst, 4), Ok(4));
+        assert_eq!(lseek(src, 0, Whence::Cur), Ok(4));
+        assert_eq!(lseek(dst, 0, Whence::Cur), Ok(4));
+        assert_eq!(copy_file_range(src, dst, 100), Ok(6));
+        assert_eq!(copy_file_range(src, dst, 100), Ok(0));
+        assert_eq!(fs::read(&dst_path).unwrap(), b"0123456789");
+
+        assert_eq!(copy_file_range(dst, src, 1), errno(wasi::ERRNO_BADF));
+        close(src).unwrap();
+        close(dst).unwrap();
+        fs::remove_file(&src_path).unwrap();
+        fs::remove_file(&dst_path).unwrap();
+    }
+
+    #[cfg(feature = "sockets")]
+    fn free_port() -> std::net::SocketAddr {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    #[cfg(feature = "sockets")]
+    #[test]
+    fn socket_loopback_echo() {
+        let addr = free_port();
+        let server = socket(AddressFamily::Inet, SocketType::Stream, 0).unwrap();
+        bind(server, &addr).unwrap();
+        listen(server, 1).unwrap();
+
+        let client = socket(AddressFamily::Inet, SocketType::Stream, 0).unwrap();
+        connect(client, &addr).unwrap();
+        let accepted = accept(server, 0).unwrap();
+        assert_eq!(write_nonblocking(client, b"ping"), Ok(Some(4)));
+        close(client).unwrap();
+        assert_eq!(read_to_string(accepted), "ping");
+
+        assert_eq!(bind(server, &addr), inval());
+        close(server).unwrap();
+        let datagram = socket(AddressFamily::Inet6, SocketType::Datagram, 0).unwrap();
+        close(datagram).unwrap();
+    }
+
+    #[cfg(feature = "sockets")]
+    #[test]
+    fn socket_connect_refused() {
+        let addr = free_port();
+        let client = socket(AddressFamily::Inet, SocketType::Stream, 0).unwrap();
+        assert_eq!(connect(client, &addr), errno(wasi::ERRNO_CONNREFUSED));
+        close(client).unwrap();
+        assert_eq!(listen(CLOSED_FD, 1), errno(wasi::ERRNO_BADF));
+    }
+}